@@ -0,0 +1,183 @@
+//! Attribute macros for lelet
+//!
+//! Use it via `lelet` crate with `macros` feature enabled,
+//! i.e. `#[lelet::main]` and `#[lelet::test]`.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{meta, parse_macro_input, Error, ItemFn, LitInt, ReturnType};
+
+/// Run async main function via `lelet::block_on`
+///
+/// ```
+/// #[lelet::main]
+/// async fn main() {
+///     println!("Hello World");
+/// }
+/// ```
+///
+/// `num_cpus = N` will call `lelet::set_num_cpus(N)` before running the function,
+/// i.e. `#[lelet::main(num_cpus = 4)]`.
+#[proc_macro_attribute]
+pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut num_cpus: Option<LitInt> = None;
+    let parser = meta::parser(|meta| {
+        if meta.path.is_ident("num_cpus") {
+            num_cpus = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported lelet::main argument, expected `num_cpus`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+
+    let input = parse_macro_input!(item as ItemFn);
+    if let Err(err) = check_async(&input, "lelet::main") {
+        return err.to_compile_error().into();
+    }
+    if input.sig.ident != "main" {
+        return Error::new_spanned(&input.sig.ident, "lelet::main can only be used on `main`")
+            .to_compile_error()
+            .into();
+    }
+    if !input.sig.inputs.is_empty() {
+        return Error::new_spanned(&input.sig.inputs, "main function can't have arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        mut sig,
+        block,
+    } = input;
+    sig.asyncness = None;
+
+    let set_num_cpus = num_cpus.map(|n| {
+        quote! {
+            ::lelet::set_num_cpus(#n).expect("lelet::main: cannot set num_cpus");
+        }
+    });
+
+    let expanded = quote! {
+        #(#attrs)*
+        #vis #sig {
+            #set_num_cpus
+            ::lelet::block_on(async move #block)
+        }
+    };
+
+    expanded.into()
+}
+
+/// Run async test function via `lelet::block_on`
+///
+/// ```
+/// #[lelet::test]
+/// async fn my_test() {
+///     assert_eq!(lelet::spawn(async { 1 }).await, 1);
+/// }
+/// # fn main() {}
+/// ```
+///
+/// `timeout_ms = N` will fail the test if it is not done in `N` milliseconds,
+/// i.e. `#[lelet::test(timeout_ms = 1000)]`.
+///
+/// Note that the executor is global to the process, so all the tests
+/// in the same binary share the same executor. `fresh_runtime` will run the test
+/// in a new process of the test binary, so it get its own executor,
+/// i.e. `#[lelet::test(fresh_runtime)]`, the test function must return `()`.
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut timeout_ms: Option<LitInt> = None;
+    let mut fresh_runtime = false;
+    let parser = meta::parser(|meta| {
+        if meta.path.is_ident("timeout_ms") {
+            timeout_ms = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("fresh_runtime") {
+            fresh_runtime = true;
+            Ok(())
+        } else {
+            Err(meta.error(
+                "unsupported lelet::test argument, expected `timeout_ms` or `fresh_runtime`",
+            ))
+        }
+    });
+    parse_macro_input!(args with parser);
+
+    let input = parse_macro_input!(item as ItemFn);
+    if let Err(err) = check_async(&input, "lelet::test") {
+        return err.to_compile_error().into();
+    }
+    if !input.sig.inputs.is_empty() {
+        return Error::new_spanned(&input.sig.inputs, "test function can't have arguments")
+            .to_compile_error()
+            .into();
+    }
+    if fresh_runtime && !matches!(input.sig.output, ReturnType::Default) {
+        return Error::new_spanned(
+            &input.sig.output,
+            "test function on fresh runtime can't return a value",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        mut sig,
+        block,
+    } = input;
+    sig.asyncness = None;
+
+    let body = match timeout_ms {
+        None => quote! {
+            ::lelet::block_on(async move #block)
+        },
+        Some(ms) => quote! {
+            match ::lelet::block_on(::lelet::time::timeout(
+                ::std::time::Duration::from_millis(#ms),
+                async move #block,
+            )) {
+                ::std::result::Result::Ok(val) => val,
+                ::std::result::Result::Err(_) => panic!("test timed out after {}ms", #ms),
+            }
+        },
+    };
+
+    let body = if fresh_runtime {
+        let name = &sig.ident;
+        quote! {
+            ::lelet::__run_in_fresh_runtime(
+                ::std::module_path!(),
+                ::std::stringify!(#name),
+                || #body,
+            )
+        }
+    } else {
+        body
+    };
+
+    let expanded = quote! {
+        #[::core::prelude::v1::test]
+        #(#attrs)*
+        #vis #sig {
+            #body
+        }
+    };
+
+    expanded.into()
+}
+
+fn check_async(input: &ItemFn, name: &str) -> Result<(), Error> {
+    if input.sig.asyncness.is_none() {
+        return Err(Error::new_spanned(
+            input.sig.fn_token,
+            format!("{} can only be used on async function", name),
+        ));
+    }
+    Ok(())
+}
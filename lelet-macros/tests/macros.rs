@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+#[lelet::test]
+async fn test() {
+    let handle = lelet::spawn(async { 1 });
+    assert_eq!(handle.await, 1);
+}
+
+#[lelet::test(timeout_ms = 1000)]
+async fn timeout() {
+    lelet::time::sleep(Duration::from_millis(10)).await;
+}
+
+#[lelet::test(timeout_ms = 10)]
+#[should_panic(expected = "test timed out after 10ms")]
+async fn timed_out() {
+    lelet::time::sleep(Duration::from_secs(60)).await;
+}
+
+#[lelet::test(fresh_runtime)]
+async fn fresh_runtime() {
+    // only possible before the executor is running
+    lelet::set_num_cpus(3).unwrap();
+    lelet::spawn(async {}).await;
+    assert_eq!(lelet::get_num_cpus(), Some(3));
+}
+
+#[lelet::test(fresh_runtime, timeout_ms = 1000)]
+async fn fresh_runtime_again() {
+    lelet::set_num_cpus(2).unwrap();
+    lelet::spawn(async {}).await;
+    assert_eq!(lelet::get_num_cpus(), Some(2));
+}
+
+#[lelet::test(fresh_runtime)]
+#[should_panic(expected = "failed on fresh runtime")]
+async fn fresh_runtime_failed() {
+    panic!("oops");
+}
+
+mod nested {
+    #[lelet::test(fresh_runtime)]
+    async fn fresh_runtime() {
+        lelet::set_num_cpus(1).unwrap();
+    }
+}
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/pass-*.rs");
+    t.compile_fail("tests/ui/fail-*.rs");
+}
@@ -0,0 +1,21 @@
+mod not_async {
+    #[lelet::main]
+    fn main() {}
+}
+
+mod not_main {
+    #[lelet::main]
+    async fn start() {}
+}
+
+mod unknown_arg {
+    #[lelet::main(threads = 2)]
+    async fn main() {}
+}
+
+mod has_args {
+    #[lelet::main]
+    async fn main(_x: u32) {}
+}
+
+fn main() {}
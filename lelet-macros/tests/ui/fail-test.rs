@@ -0,0 +1,15 @@
+#[lelet::test]
+fn not_async() {}
+
+#[lelet::test(fresh_runtime)]
+async fn returns_value() -> Result<(), ()> {
+    Ok(())
+}
+
+#[lelet::test(retries = 3)]
+async fn unknown_arg() {}
+
+#[lelet::test]
+async fn has_args(_x: u32) {}
+
+fn main() {}
@@ -0,0 +1,5 @@
+#[lelet::main(num_cpus = 2)]
+async fn main() {
+    assert_eq!(lelet::spawn(async { 1 }).await, 1);
+    assert_eq!(lelet::get_num_cpus(), Some(2));
+}
@@ -0,0 +1,129 @@
+//! Compare the current `block_on` with the previous implementation
+//! that allocate `Arc<Parker>` with `Mutex` and `Condvar` on every call
+//!
+//! run with `cargo bench -p lelet-utils --bench block_on`
+
+use std::future::Future;
+use std::mem::forget;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lelet_utils::{block_on, Yields};
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn old_block_on<F: Future>(mut f: F) -> F::Output {
+    #[allow(clippy::mutex_atomic)]
+    #[derive(Default)]
+    struct Parker(Mutex<bool>, Condvar);
+
+    #[allow(clippy::mutex_atomic)]
+    impl Parker {
+        fn unpark(self: &Parker) {
+            *self.0.lock().unwrap() = true;
+            self.1.notify_one();
+        }
+
+        fn park(self: &Parker) {
+            let mut runnable = self.0.lock().unwrap();
+            while !*runnable {
+                runnable = self.1.wait(runnable).unwrap();
+            }
+            *runnable = false;
+        }
+    }
+
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |parker| unsafe {
+            let parker = Arc::from_raw(parker as *const Parker);
+            let cloned_parker = parker.clone();
+            forget(parker);
+            RawWaker::new(Arc::into_raw(cloned_parker) as *const (), &VTABLE)
+        },
+        |parker| unsafe { Arc::from_raw(parker as *const Parker).unpark() },
+        |parker| unsafe { (*(parker as *const Parker)).unpark() },
+        |parker| unsafe { drop(Arc::from_raw(parker as *const Parker)) },
+    );
+
+    let parker = Arc::new(Parker::default());
+
+    let waker = unsafe {
+        Waker::from_raw(RawWaker::new(
+            Arc::into_raw(parker.clone()) as *const (),
+            &VTABLE,
+        ))
+    };
+
+    let mut f = unsafe { Pin::new_unchecked(&mut f) };
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match f.as_mut().poll(&mut cx) {
+            Poll::Pending => parker.park(),
+            Poll::Ready(val) => return val,
+        }
+    }
+}
+
+/// future that is woken from another thread
+struct RemoteWake(bool);
+
+impl Future for RemoteWake {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        let waker = cx.waker().clone();
+        thread::spawn(move || waker.wake());
+        Poll::Pending
+    }
+}
+
+fn bench(name: &str, iterations: u32, mut f: impl FnMut()) {
+    // warm up
+    for _ in 0..iterations / 10 {
+        f();
+    }
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{:<32} {:>10.1} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / iterations as f64
+    );
+}
+
+fn main() {
+    bench("old: ready", ITERATIONS, || old_block_on(async {}));
+    bench("new: ready", ITERATIONS, || block_on(async {}));
+
+    bench("old: yield once", ITERATIONS, || old_block_on(Yields(1)));
+    bench("new: yield once", ITERATIONS, || block_on(Yields(1)));
+
+    bench("old: yield 10 times", ITERATIONS / 10, || {
+        old_block_on(Yields(10))
+    });
+    bench("new: yield 10 times", ITERATIONS / 10, || {
+        block_on(Yields(10))
+    });
+
+    bench("old: woken by other thread", ITERATIONS / 100, || {
+        old_block_on(RemoteWake(false))
+    });
+    bench("new: woken by other thread", ITERATIONS / 100, || {
+        block_on(RemoteWake(false))
+    });
+
+    // make sure the spawned threads are done
+    thread::sleep(Duration::from_millis(100));
+}
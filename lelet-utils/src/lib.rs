@@ -1,14 +1,20 @@
-use std::cell::UnsafeCell;
+use std::cell::{Cell, UnsafeCell};
+use std::error::Error;
 use std::fmt;
 use std::future::Future;
 use std::marker::PhantomData;
-use std::mem::forget;
+use std::mem::{forget, ManuallyDrop};
 use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
 use std::process::abort;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::Arc;
 use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::time::{Duration, Instant};
+
+mod parker;
+
+use parker::Parker;
 
 /// Call [`abort`] when `f` panic
 ///
@@ -109,7 +115,7 @@ impl<T> SimpleLock<T> {
     }
 }
 
-impl<T: ?Sized + Default> Default for SimpleLock<T> {
+impl<T: Default> Default for SimpleLock<T> {
     #[inline(always)]
     fn default() -> SimpleLock<T> {
         SimpleLock::new(T::default())
@@ -119,7 +125,7 @@ impl<T: ?Sized + Default> Default for SimpleLock<T> {
 impl<T: ?Sized> SimpleLock<T> {
     /// Try to lock.
     #[inline(always)]
-    pub fn try_lock(&self) -> Option<SimpleLockGuard<T>> {
+    pub fn try_lock(&self) -> Option<SimpleLockGuard<'_, T>> {
         if self.locked.swap(true, Ordering::Acquire) {
             None
         } else {
@@ -188,30 +194,55 @@ impl<T: ?Sized + fmt::Debug> fmt::Debug for SimpleLockGuard<'_, T> {
 }
 
 /// Block current thread until f is complete
+///
+/// The parker is cached in thread local, so it doesn't allocate,
+/// except when it is called recursively.
 #[inline(always)]
-pub fn block_on<F: Future>(mut f: F) -> F::Output {
-    // originally copied from `extreme` (https://docs.rs/extreme)
+pub fn block_on<F: Future>(f: F) -> F::Output {
+    match run(f, None) {
+        Ok(val) => val,
+        Err(Elapsed) => unreachable!(), // no deadline
+    }
+}
 
-    #[allow(clippy::mutex_atomic)]
-    #[derive(Default)]
-    struct Parker(Mutex<bool>, Condvar);
+/// Block current thread until f is complete, or `timeout` has elapsed
+///
+/// `f` is polled at least once, and it is dropped when the timeout has elapsed.
+#[inline(always)]
+pub fn block_on_timeout<F: Future>(f: F, timeout: Duration) -> Result<F::Output, Elapsed> {
+    // too far in the future, just treat it as no deadline
+    run(f, Instant::now().checked_add(timeout))
+}
 
-    #[allow(clippy::mutex_atomic)]
-    impl Parker {
-        #[inline(always)]
-        fn unpark(self: &Parker) {
-            *self.0.lock().unwrap() = true;
-            self.1.notify_one();
-        }
+/// Block current thread until f is complete, or `deadline` is reached
+///
+/// `f` is polled at least once, and it is dropped when the deadline is reached.
+#[inline(always)]
+pub fn block_on_deadline<F: Future>(f: F, deadline: Instant) -> Result<F::Output, Elapsed> {
+    run(f, Some(deadline))
+}
 
-        #[inline(always)]
-        fn park(self: &Parker) {
-            let mut runnable = self.0.lock().unwrap();
-            while !*runnable {
-                runnable = self.1.wait(runnable).unwrap();
-            }
-            *runnable = false;
-        }
+/// Error returned by [`block_on_timeout`] and [`block_on_deadline`]
+///
+/// [`block_on_timeout`]: fn.block_on_timeout.html
+/// [`block_on_deadline`]: fn.block_on_deadline.html
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+#[inline(always)]
+fn run<F: Future>(mut f: F, deadline: Option<Instant>) -> Result<F::Output, Elapsed> {
+    // originally copied from `extreme` (https://docs.rs/extreme)
+
+    thread_local! {
+        static CACHE: Cell<Option<Arc<Parker>>> = const { Cell::new(None) };
     }
 
     static VTABLE: RawWakerVTable = RawWakerVTable::new(
@@ -219,10 +250,8 @@ pub fn block_on<F: Future>(mut f: F) -> F::Output {
         // clone: unsafe fn(*const ()) -> RawWaker
         #[inline(always)]
         |parker| unsafe {
-            let parker = Arc::from_raw(parker as *const Parker);
-            let cloned_parker = parker.clone();
-            forget(parker);
-            RawWaker::new(Arc::into_raw(cloned_parker) as *const (), &VTABLE)
+            Arc::increment_strong_count(parker as *const Parker);
+            RawWaker::new(parker, &VTABLE)
         },
         //
         // wake: unsafe fn(*const ())
@@ -231,28 +260,197 @@ pub fn block_on<F: Future>(mut f: F) -> F::Output {
         //
         // wake_by_ref: unsafe fn(*const ())
         #[inline(always)]
-        |parker| unsafe { (&*(parker as *const Parker)).unpark() },
+        |parker| unsafe { (*(parker as *const Parker)).unpark() },
         //
         // drop: unsafe fn(*const ())
         #[inline(always)]
-        |parker| unsafe { drop(Arc::from_raw(parker as *const Parker)) },
+        |parker| unsafe { Arc::decrement_strong_count(parker as *const Parker) },
     );
 
-    let parker = Arc::new(Parker::default());
+    // take the cached parker, it is `None` when we are called recursively
+    let parker = CACHE
+        .try_with(Cell::take)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| Arc::new(Parker::new()));
 
-    let waker = unsafe {
-        Waker::from_raw(RawWaker::new(
-            Arc::into_raw(parker.clone()) as *const (),
-            &VTABLE,
-        ))
-    };
+    // put back the parker to the cache when done (or panic)
+    struct PutBack(Option<Arc<Parker>>);
+
+    impl Drop for PutBack {
+        #[inline(always)]
+        fn drop(&mut self) {
+            let parker = self.0.take();
+            let _ = CACHE.try_with(|cache| cache.set(parker));
+        }
+    }
+
+    let parker = PutBack(Some(parker));
+    let parker = parker.0.as_ref().unwrap();
+
+    // the waker borrow our reference, so it must not be dropped,
+    // only its clones (if any) own the reference
+    let waker = ManuallyDrop::new(unsafe {
+        Waker::from_raw(RawWaker::new(Arc::as_ptr(parker) as *const (), &VTABLE))
+    });
 
     let mut f = unsafe { Pin::new_unchecked(&mut f) };
     let mut cx = Context::from_waker(&waker);
     loop {
         match f.as_mut().poll(&mut cx) {
-            Poll::Pending => parker.park(),
-            Poll::Ready(val) => return val,
+            Poll::Ready(val) => return Ok(val),
+            Poll::Pending => match deadline {
+                None => parker.park(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Elapsed);
+                    }
+                    parker.park_timeout(deadline - now);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+    use std::thread;
+
+    use super::*;
+
+    /// return the waker of the current block_on
+    struct GetWaker;
+
+    impl Future for GetWaker {
+        type Output = Waker;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Waker> {
+            Poll::Ready(cx.waker().clone())
+        }
+    }
+
+    #[test]
+    fn wake_from_other_thread() {
+        let mut handle = None;
+        let val = block_on(poll_fn(|cx| match handle.take() {
+            None => {
+                let waker = cx.waker().clone();
+                handle = Some(thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(10));
+                    waker.wake();
+                }));
+                Poll::Pending
+            }
+            Some(handle) => {
+                handle.join().unwrap();
+                Poll::Ready(1)
+            }
+        }));
+        assert_eq!(val, 1);
+
+        block_on(Yields(10));
+    }
+
+    #[test]
+    fn cached_parker() {
+        let waker = block_on(GetWaker);
+        assert!(block_on(GetWaker).will_wake(&waker));
+
+        // put back even when panic
+        let result = panic::catch_unwind(|| {
+            block_on(async {
+                assert!(GetWaker.await.will_wake(&waker));
+                panic!("oops");
+            })
+        });
+        assert!(result.is_err());
+        assert!(block_on(GetWaker).will_wake(&waker));
+
+        // each thread has its own parker
+        let other = thread::spawn(|| block_on(GetWaker)).join().unwrap();
+        assert!(!other.will_wake(&waker));
+    }
+
+    #[test]
+    fn recursive() {
+        let outer = block_on(GetWaker);
+
+        let (inner, val) = block_on(async {
+            let waker = GetWaker.await;
+            assert!(waker.will_wake(&outer));
+
+            // the cached parker is in use, the inner one has its own
+            let inner = block_on(GetWaker);
+            assert!(!inner.will_wake(&outer));
+
+            (inner, block_on(async { block_on(async { 1 }) + 1 }))
+        });
+        assert!(!inner.will_wake(&outer));
+        assert_eq!(val, 2);
+
+        // the cache is still the outer one
+        assert!(block_on(GetWaker).will_wake(&outer));
+    }
+
+    #[test]
+    fn timeout() {
+        struct SetOnDrop<'a>(&'a Cell<bool>);
+
+        impl Drop for SetOnDrop<'_> {
+            fn drop(&mut self) {
+                self.0.set(true);
+            }
         }
+
+        // pending forever, dropped after the timeout
+        let dropped = Cell::new(false);
+        let start = Instant::now();
+        let guard = SetOnDrop(&dropped);
+        let result = block_on_timeout(
+            poll_fn(move |_| {
+                let _ = &guard;
+                Poll::<()>::Pending
+            }),
+            Duration::from_millis(50),
+        );
+        assert_eq!(result, Err(Elapsed));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(dropped.get());
+
+        assert_eq!(block_on_timeout(Yields(3), Duration::from_secs(60)), Ok(()));
+
+        // too far in the future, no deadline
+        assert_eq!(block_on_timeout(Yields(3), Duration::MAX), Ok(()));
+    }
+
+    #[test]
+    fn deadline() {
+        // already passed, but still polled once
+        let past = Instant::now();
+        assert_eq!(block_on_deadline(async { 1 }, past), Ok(1));
+        assert_eq!(block_on_deadline(Yields(1), past), Err(Elapsed));
+
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let result = block_on_deadline(poll_fn(|_| Poll::<()>::Pending), deadline);
+        assert_eq!(result, Err(Elapsed));
+        assert!(Instant::now() >= deadline);
+    }
+
+    fn poll_fn<T>(f: impl FnMut(&mut Context) -> Poll<T>) -> impl Future<Output = T> {
+        struct PollFn<F>(F);
+
+        impl<F> Unpin for PollFn<F> {}
+
+        impl<T, F: FnMut(&mut Context) -> Poll<T>> Future for PollFn<F> {
+            type Output = T;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+                (self.0)(cx)
+            }
+        }
+
+        PollFn(f)
     }
 }
@@ -0,0 +1,234 @@
+//! Thread parker used by [`block_on`]
+//!
+//! futex based on linux, `Mutex` and `Condvar` based on other platform
+//!
+//! [`block_on`]: ../fn.block_on.html
+
+pub use imp::Parker;
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::ptr;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::time::Duration;
+
+    const EMPTY: i32 = 0;
+    const NOTIFIED: i32 = 1;
+    const PARKED: i32 = -1;
+
+    pub struct Parker {
+        state: AtomicI32,
+    }
+
+    impl Parker {
+        #[inline(always)]
+        pub fn new() -> Parker {
+            Parker {
+                state: AtomicI32::new(EMPTY),
+            }
+        }
+
+        #[inline(always)]
+        pub fn park(&self) {
+            // NOTIFIED => EMPTY, or EMPTY => PARKED
+            if self.state.fetch_sub(1, Ordering::Acquire) == NOTIFIED {
+                return;
+            }
+
+            loop {
+                futex_wait(&self.state, PARKED);
+
+                if self
+                    .state
+                    .compare_exchange(NOTIFIED, EMPTY, Ordering::Acquire, Ordering::Acquire)
+                    .is_ok()
+                {
+                    return;
+                }
+
+                // spurious wake up
+            }
+        }
+
+        /// may return spuriously before `timeout` even when not unparked
+        #[inline(always)]
+        pub fn park_timeout(&self, timeout: Duration) {
+            // NOTIFIED => EMPTY, or EMPTY => PARKED
+            if self.state.fetch_sub(1, Ordering::Acquire) == NOTIFIED {
+                return;
+            }
+
+            futex_wait_timeout(&self.state, PARKED, timeout);
+
+            // NOTIFIED or PARKED (timeout or spurious) => EMPTY
+            self.state.swap(EMPTY, Ordering::Acquire);
+        }
+
+        #[inline(always)]
+        pub fn unpark(&self) {
+            if self.state.swap(NOTIFIED, Ordering::Release) == PARKED {
+                futex_wake(&self.state);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn futex_wait(futex: &AtomicI32, expected: i32) {
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                futex as *const AtomicI32,
+                libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+                expected,
+                ptr::null::<libc::timespec>(),
+            );
+        }
+    }
+
+    #[inline(always)]
+    fn futex_wait_timeout(futex: &AtomicI32, expected: i32, timeout: Duration) {
+        let timeout = libc::timespec {
+            tv_sec: std::cmp::min(timeout.as_secs(), libc::time_t::MAX as u64) as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        };
+
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                futex as *const AtomicI32,
+                libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+                expected,
+                &timeout as *const libc::timespec,
+            );
+        }
+    }
+
+    #[inline(always)]
+    fn futex_wake(futex: &AtomicI32) {
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                futex as *const AtomicI32,
+                libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+                1,
+            );
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::sync::{Condvar, Mutex};
+    use std::time::Duration;
+
+    pub struct Parker {
+        notified: Mutex<bool>,
+        cond: Condvar,
+    }
+
+    #[allow(clippy::mutex_atomic)]
+    impl Parker {
+        #[inline(always)]
+        pub fn new() -> Parker {
+            Parker {
+                notified: Mutex::new(false),
+                cond: Condvar::new(),
+            }
+        }
+
+        #[inline(always)]
+        pub fn park(&self) {
+            let mut notified = self.notified.lock().unwrap();
+            while !*notified {
+                notified = self.cond.wait(notified).unwrap();
+            }
+            *notified = false;
+        }
+
+        /// may return spuriously before `timeout` even when not unparked
+        #[inline(always)]
+        pub fn park_timeout(&self, timeout: Duration) {
+            let mut notified = self.notified.lock().unwrap();
+            if !*notified {
+                notified = self.cond.wait_timeout(notified, timeout).unwrap().0;
+            }
+            *notified = false;
+        }
+
+        #[inline(always)]
+        pub fn unpark(&self) {
+            *self.notified.lock().unwrap() = true;
+            self.cond.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn unpark_before_park() {
+        let parker = Parker::new();
+        parker.unpark();
+        parker.unpark();
+        parker.park();
+    }
+
+    #[test]
+    fn unpark_from_other_thread() {
+        let parker = Arc::new(Parker::new());
+
+        for _ in 0..100 {
+            let handle = thread::spawn({
+                let parker = parker.clone();
+                move || parker.unpark()
+            });
+            parker.park();
+            handle.join().unwrap();
+        }
+
+        let handle = thread::spawn({
+            let parker = parker.clone();
+            move || {
+                thread::sleep(Duration::from_millis(10));
+                parker.unpark()
+            }
+        });
+        parker.park();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn park_timeout() {
+        let parker = Parker::new();
+
+        // the notification is consumed
+        parker.unpark();
+        parker.park_timeout(Duration::from_secs(60));
+
+        // may return spuriously, but not too early on average
+        let start = Instant::now();
+        for _ in 0..5 {
+            parker.park_timeout(Duration::from_millis(10));
+        }
+        assert!(start.elapsed() >= Duration::from_millis(10));
+
+        let parker = Arc::new(Parker::new());
+        let handle = thread::spawn({
+            let parker = parker.clone();
+            move || {
+                thread::sleep(Duration::from_millis(10));
+                parker.unpark()
+            }
+        });
+        let start = Instant::now();
+        parker.park_timeout(Duration::from_secs(60));
+        assert!(start.elapsed() < Duration::from_secs(60));
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Spawn-heavy workloads for the scheduler
+//!
+//! run with `cargo bench -p lelet --bench spawn`
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 5;
+
+fn bench(name: &str, ops: u64, f: impl Fn()) {
+    // warm up
+    f();
+
+    let mut best = Duration::from_secs(u64::MAX);
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        f();
+        best = std::cmp::min(best, start.elapsed());
+    }
+
+    println!(
+        "{:<40} {:>10.1} ns/op",
+        name,
+        best.as_nanos() as f64 / ops as f64
+    );
+}
+
+/// spawn `n` tasks from inside a task, then await them all
+fn spawn_many(n: u64) {
+    lelet::block_on(lelet::spawn(async move {
+        let handles: Vec<_> = (0..n).map(|i| lelet::spawn(async move { i })).collect();
+        let mut sum = 0;
+        for h in handles {
+            sum += h.await;
+        }
+        assert_eq!(sum, n * (n - 1) / 2);
+    }));
+}
+
+/// same as `spawn_many`, but via `spawn_batch`
+fn spawn_batch_many(n: u64) {
+    lelet::block_on(lelet::spawn(async move {
+        let handles = lelet::spawn_batch((0..n).map(|i| async move { i }));
+        let mut sum = 0;
+        for h in handles {
+            sum += h.await;
+        }
+        assert_eq!(sum, n * (n - 1) / 2);
+    }));
+}
+
+/// spawn `n` tasks from outside the executor, they go to the global queue
+fn spawn_outside(n: u64, batch: bool) {
+    let handles: Vec<_> = if batch {
+        lelet::spawn_batch((0..n).map(|i| async move { i }))
+    } else {
+        (0..n).map(|i| lelet::spawn(async move { i })).collect()
+    };
+    let mut sum = 0;
+    for h in handles {
+        sum += lelet::block_on(h);
+    }
+    assert_eq!(sum, n * (n - 1) / 2);
+}
+
+/// every task spawn `width` children until `depth` is reached
+fn spawn_tree(depth: u32, width: u32) -> Pin<Box<dyn Future<Output = u64> + Send>> {
+    Box::pin(async move {
+        if depth == 0 {
+            return 1;
+        }
+        let handles: Vec<_> = (0..width)
+            .map(|_| lelet::spawn(spawn_tree(depth - 1, width)))
+            .collect();
+        let mut count = 1;
+        for h in handles {
+            count += h.await;
+        }
+        count
+    })
+}
+
+/// two tasks waking each other via channel
+fn ping_pong(n: u64) {
+    let (ping_tx, ping_rx) = lelet::chan::bounded(1);
+    let (pong_tx, pong_rx) = lelet::chan::bounded(1);
+
+    let pong = lelet::spawn(async move {
+        while let Ok(i) = ping_rx.recv().await {
+            pong_tx.send(i).await.unwrap();
+        }
+    });
+
+    lelet::block_on(lelet::spawn(async move {
+        for i in 0..n {
+            ping_tx.send(i).await.unwrap();
+            assert_eq!(pong_rx.recv().await.unwrap(), i);
+        }
+    }));
+
+    lelet::block_on(pong);
+}
+
+/// many tasks yielding at the same time
+fn yield_many(tasks: u64, yields: u64) {
+    lelet::block_on(lelet::spawn(async move {
+        let handles: Vec<_> = (0..tasks)
+            .map(|_| {
+                lelet::spawn(async move {
+                    for _ in 0..yields {
+                        lelet::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.await;
+        }
+    }));
+}
+
+fn main() {
+    bench("spawn 100k from a task", 100_000, || spawn_many(100_000));
+    bench("spawn_batch 100k from a task", 100_000, || {
+        spawn_batch_many(100_000)
+    });
+
+    bench("spawn 100k from outside", 100_000, || {
+        spawn_outside(100_000, false)
+    });
+    bench("spawn_batch 100k from outside", 100_000, || {
+        spawn_outside(100_000, true)
+    });
+
+    bench("spawn tree (depth 5, width 8)", 37_449, || {
+        assert_eq!(lelet::block_on(spawn_tree(5, 8)), 37_449);
+    });
+
+    bench("ping pong 100k", 200_000, || ping_pong(100_000));
+
+    bench("yield 1k tasks x 100", 100_000, || yield_many(1_000, 100));
+}
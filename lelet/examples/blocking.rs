@@ -0,0 +1,22 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn main() {
+    lelet::set_num_cpus(1).unwrap();
+
+    let start = Instant::now();
+
+    let blocked = lelet::spawn(async move {
+        // the processor is handed off as soon as the other task is pushed,
+        // no need to wait for the blocking detection
+        lelet::blocking(|| thread::sleep(Duration::from_millis(100)));
+        println!("Blocking task done after {:?}", start.elapsed());
+    });
+
+    let other = lelet::spawn(async move {
+        println!("Other task is running after {:?}", start.elapsed());
+    });
+
+    lelet::block_on(other);
+    lelet::block_on(blocked);
+}
@@ -0,0 +1,56 @@
+use std::thread;
+use std::time::Duration;
+
+use lelet::chan;
+
+fn main() {
+    simple_logger::init().unwrap();
+
+    // rendezvous channel, the sender wait until the message is received
+    let (ping_tx, ping_rx) = chan::bounded(0);
+    let (pong_tx, pong_rx) = chan::bounded(0);
+
+    lelet::spawn(async move {
+        while let Ok(i) = ping_rx.recv().await {
+            println!("ping {}", i);
+            pong_tx.send(i).await.unwrap();
+        }
+    });
+
+    // unbounded channel, fed from non-executor thread
+    let (tick_tx, tick_rx) = chan::unbounded();
+    thread::spawn(move || {
+        for i in 0..3 {
+            thread::sleep(Duration::from_millis(50));
+            tick_tx.send_blocking(i).unwrap();
+        }
+    });
+
+    // nobody receive from this channel
+    let (idle_tx, idle_rx) = chan::bounded(0);
+
+    lelet::block_on(async move {
+        for i in 0..3 {
+            ping_tx.send(i).await.unwrap();
+            println!("pong {}", pong_rx.recv().await.unwrap());
+        }
+
+        loop {
+            lelet::select! {
+                tick = tick_rx.recv() => match tick {
+                    Ok(i) => println!("tick {}", i),
+                    Err(_) => break,
+                },
+                _ = idle_tx.send(100) => unreachable!("nobody receive from idle channel"),
+            }
+        }
+
+        // the pending send is canceled when select! is done
+        assert!(idle_rx.try_recv().is_err());
+
+        lelet::select! {
+            _ = pong_rx.recv() => unreachable!(),
+            default => println!("nothing is ready"),
+        }
+    });
+}
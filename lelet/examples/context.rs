@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use lelet::time::sleep;
+use lelet::Context;
+
+struct RequestId(u32);
+
+async fn worker(n: u32) {
+    let ctx = Context::current();
+    let id = ctx.value::<RequestId>().unwrap().0;
+
+    for i in 0.. {
+        if let Some(err) = ctx.err() {
+            println!("request {} worker {} stopped: {}", id, n, err);
+            return;
+        }
+        println!("request {} worker {} step {}", id, n, i);
+        sleep(Duration::from_millis(100)).await;
+    }
+}
+
+fn main() {
+    simple_logger::init().unwrap();
+
+    lelet::block_on(async {
+        let (ctx, cancel) = Context::background().with_timeout(Duration::from_millis(350));
+        let ctx = ctx.with_value(RequestId(1));
+
+        // the workers inherit the context
+        let handles: Vec<_> = (0..3).map(|n| ctx.spawn(worker(n))).collect();
+        for h in handles {
+            h.await;
+        }
+
+        cancel.cancel();
+    });
+}
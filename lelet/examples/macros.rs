@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+use lelet::time::sleep;
+
+#[lelet::main(num_cpus = 2)]
+async fn main() {
+    simple_logger::init().unwrap();
+
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            lelet::spawn(async move {
+                sleep(Duration::from_millis(100 * i)).await;
+                println!("Hello World {} from {:?} cpus", i, lelet::get_num_cpus());
+            })
+        })
+        .collect();
+
+    for h in handles {
+        h.await;
+    }
+}
@@ -0,0 +1,23 @@
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    simple_logger::init().unwrap();
+
+    let mut data = vec![1, 2, 3, 4, 5];
+    let name = String::from("scoped");
+
+    lelet::scope(|s| {
+        for x in &mut data {
+            let name = &name;
+            s.spawn(async move {
+                // blocking is still okay here
+                thread::sleep(Duration::from_millis(100));
+                *x *= 2;
+                println!("{} task done: {}", name, x);
+            });
+        }
+    });
+
+    println!("all done: {:?}", data);
+}
@@ -0,0 +1,22 @@
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    simple_logger::init().unwrap();
+
+    lelet::block_on(async {
+        // at most 2 task running at once
+        let mut group = lelet::TaskGroup::with_limit(2);
+
+        for i in (0..6).rev() {
+            group.spawn(async move {
+                thread::sleep(Duration::from_millis(100 * i));
+                i
+            });
+        }
+
+        while let Some(i) = group.join_next().await {
+            println!("task {} is done, {} left", i, group.len());
+        }
+    });
+}
@@ -0,0 +1,122 @@
+use std::error::Error;
+use std::fmt;
+
+/// Error returned by [`Sender::send`], all the [`Receiver`] are dropped
+///
+/// the message is returned back
+///
+/// [`Sender::send`]: struct.Sender.html#method.send
+/// [`Receiver`]: struct.Receiver.html
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+impl<T> SendError<T> {
+    /// Get the message back
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("sending on a disconnected channel")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+/// Error returned by [`Sender::try_send`]
+///
+/// the message is returned back
+///
+/// [`Sender::try_send`]: struct.Sender.html#method.try_send
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    /// The channel is full, or no receiver is waiting on rendezvous channel
+    Full(T),
+
+    /// All the [`Receiver`] are dropped
+    ///
+    /// [`Receiver`]: struct.Receiver.html
+    Disconnected(T),
+}
+
+impl<T> TrySendError<T> {
+    /// Get the message back
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(msg) => msg,
+            TrySendError::Disconnected(msg) => msg,
+        }
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("sending on a full channel"),
+            TrySendError::Disconnected(_) => f.write_str("sending on a disconnected channel"),
+        }
+    }
+}
+
+impl<T> Error for TrySendError<T> {}
+
+/// Error returned by [`Receiver::recv`], the channel is empty and all the [`Sender`] are dropped
+///
+/// [`Receiver::recv`]: struct.Receiver.html#method.recv
+/// [`Sender`]: struct.Sender.html
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("receiving on an empty and disconnected channel")
+    }
+}
+
+impl Error for RecvError {}
+
+/// Error returned by [`Receiver::try_recv`]
+///
+/// [`Receiver::try_recv`]: struct.Receiver.html#method.try_recv
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TryRecvError {
+    /// The channel is empty
+    Empty,
+
+    /// The channel is empty and all the [`Sender`] are dropped
+    ///
+    /// [`Sender`]: struct.Sender.html
+    Disconnected,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+            TryRecvError::Disconnected => {
+                f.write_str("receiving on an empty and disconnected channel")
+            }
+        }
+    }
+}
+
+impl Error for TryRecvError {}
@@ -0,0 +1,625 @@
+//! Golang like channel
+//!
+//! Multi-producer multi-consumer channel, the channel can be [`bounded`] or [`unbounded`].
+//! Bounded channel with capacity 0 is rendezvous channel (unbuffered channel in golang),
+//! the sender will wait until there is a receiver waiting for the message.
+//!
+//! Unlike golang, there is no `close`, the channel is closed when all the [`Sender`] are dropped
+//! (or all the [`Receiver`] are dropped).
+//!
+//! Use [`select!`] to wait on multiple channel operations.
+//!
+//! [`bounded`]: fn.bounded.html
+//! [`unbounded`]: fn.unbounded.html
+//! [`Sender`]: struct.Sender.html
+//! [`Receiver`]: struct.Receiver.html
+//! [`select!`]: ../macro.select.html
+
+mod error;
+mod select;
+
+pub use error::{RecvError, SendError, TryRecvError, TrySendError};
+
+#[doc(hidden)]
+pub use select::{__poll_fn, __random};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::executor::block_on;
+use crate::waiters::Waiters;
+
+/// Create a channel with capacity `cap`
+///
+/// if `cap` is 0, the channel is rendezvous channel
+#[inline(always)]
+pub fn bounded<T>(cap: usize) -> (Sender<T>, Receiver<T>) {
+    new(Some(cap))
+}
+
+/// Create a channel with unlimited capacity
+#[inline(always)]
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    new(None)
+}
+
+#[inline(always)]
+fn new<T>(cap: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let chan = Arc::new(Chan {
+        cap,
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            senders: 1,
+            receivers: 1,
+            reserved: 0,
+            send_waiters: Waiters::new(),
+            recv_waiters: Waiters::new(),
+        }),
+    });
+
+    (Sender(chan.clone()), Receiver(chan))
+}
+
+struct Chan<T> {
+    cap: Option<usize>,
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+
+    senders: usize,
+    receivers: usize,
+
+    /// number of receiver that is notified by a new message and not done yet,
+    /// on rendezvous channel, every message in the queue is reserved for one of them
+    reserved: usize,
+
+    send_waiters: Waiters,
+    recv_waiters: Waiters,
+}
+
+impl<T> Chan<T> {
+    #[inline(always)]
+    fn is_rendezvous(&self) -> bool {
+        self.cap == Some(0)
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        if self.is_rendezvous() {
+            0
+        } else {
+            self.state.lock().unwrap().queue.len()
+        }
+    }
+
+    #[inline(always)]
+    fn try_send(&self, msg: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.receivers == 0 {
+            return Err(TrySendError::Disconnected(msg));
+        }
+
+        let full = match self.cap {
+            None => false,
+
+            // only success if there is receiver waiting for it
+            Some(0) => !state.has_receiver_waiting(),
+
+            Some(cap) => state.queue.len() >= cap,
+        };
+
+        if full {
+            return Err(TrySendError::Full(msg));
+        }
+
+        state.push(msg);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.pop() {
+            Some(msg) => Ok(msg),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+impl<T> State<T> {
+    #[inline(always)]
+    fn push(&mut self, msg: T) {
+        self.queue.push_back(msg);
+        if self.recv_waiters.notify_one() {
+            self.reserved += 1;
+        }
+    }
+
+    #[inline(always)]
+    fn pop(&mut self) -> Option<T> {
+        let msg = self.queue.pop_front()?;
+        self.send_waiters.notify_one();
+        Some(msg)
+    }
+
+    /// is there a receiver that is waiting and not notified yet,
+    /// rendezvous channel only commit the message to such receiver
+    #[inline(always)]
+    fn has_receiver_waiting(&self) -> bool {
+        self.recv_waiters.len() > 0
+    }
+
+    /// the receiver is notified and done with it, successfully or not
+    ///
+    /// saturating, because the receivers are also notified when all the senders are dropped
+    #[inline(always)]
+    fn release(&mut self) {
+        self.reserved = self.reserved.saturating_sub(1);
+    }
+}
+
+/// The sending side of a channel
+pub struct Sender<T>(Arc<Chan<T>>);
+
+impl<T> Sender<T> {
+    /// Send the message, wait if the channel is full
+    ///
+    /// for rendezvous channel, wait until there is a receiver waiting for it,
+    /// the message is not in the channel until then, so dropping the future
+    /// always give back the message
+    ///
+    /// return error if all the [`Receiver`] are dropped
+    ///
+    /// [`Receiver`]: struct.Receiver.html
+    #[inline(always)]
+    pub fn send(&self, msg: T) -> Send<'_, T> {
+        Send {
+            chan: &self.0,
+            msg: Some(msg),
+            key: None,
+        }
+    }
+
+    /// Send the message, blocking current thread if the channel is full
+    ///
+    /// This is intended to be used from outside the executor thread
+    #[inline(always)]
+    pub fn send_blocking(&self, msg: T) -> Result<(), SendError<T>> {
+        block_on(self.send(msg))
+    }
+
+    /// Try to send the message without waiting
+    #[inline(always)]
+    pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>> {
+        self.0.try_send(msg)
+    }
+
+    /// Number of message in the channel
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Return `true` if there is no message in the channel
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.0.len() == 0
+    }
+
+    /// Capacity of the channel, `None` if the channel is unbounded
+    #[inline(always)]
+    pub fn capacity(&self) -> Option<usize> {
+        self.0.cap
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    #[inline(always)]
+    fn clone(&self) -> Sender<T> {
+        self.0.state.lock().unwrap().senders += 1;
+        Sender(self.0.clone())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.recv_waiters.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    #[inline(always)]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Sender { .. }")
+    }
+}
+
+/// The receiving side of a channel
+pub struct Receiver<T>(Arc<Chan<T>>);
+
+impl<T> Receiver<T> {
+    /// Receive a message, wait if the channel is empty
+    ///
+    /// return error if the channel is empty and all the [`Sender`] are dropped
+    ///
+    /// for rendezvous channel, the sender is done as soon as the message is committed to
+    /// a waiting receiver, if the future is dropped after that, the message is passed to
+    /// the next waiting receiver, or dropped if there is none
+    ///
+    /// [`Sender`]: struct.Sender.html
+    #[inline(always)]
+    pub fn recv(&self) -> Recv<'_, T> {
+        Recv {
+            chan: &self.0,
+            key: None,
+        }
+    }
+
+    /// Receive a message, blocking current thread if the channel is empty
+    ///
+    /// This is intended to be used from outside the executor thread
+    #[inline(always)]
+    pub fn recv_blocking(&self) -> Result<T, RecvError> {
+        block_on(self.recv())
+    }
+
+    /// Try to receive a message without waiting
+    #[inline(always)]
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    /// Number of message in the channel
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Return `true` if there is no message in the channel
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.0.len() == 0
+    }
+
+    /// Capacity of the channel, `None` if the channel is unbounded
+    #[inline(always)]
+    pub fn capacity(&self) -> Option<usize> {
+        self.0.cap
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    #[inline(always)]
+    fn clone(&self) -> Receiver<T> {
+        self.0.state.lock().unwrap().receivers += 1;
+        Receiver(self.0.clone())
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            state.send_waiters.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    #[inline(always)]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Receiver { .. }")
+    }
+}
+
+/// Future returned by [`Sender::send`]
+///
+/// [`Sender::send`]: struct.Sender.html#method.send
+pub struct Send<'a, T> {
+    chan: &'a Chan<T>,
+    msg: Option<T>,
+    key: Option<usize>,
+}
+
+// we never pin the message
+impl<T> Unpin for Send<'_, T> {}
+
+impl<T> Future for Send<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.chan.state.lock().unwrap();
+
+        macro_rules! ready {
+            ($val:expr) => {{
+                if let Some(key) = this.key.take() {
+                    state.send_waiters.remove(key);
+                }
+                return Poll::Ready($val);
+            }};
+        }
+
+        let msg = this.msg.take().expect("Send polled after completion");
+
+        if state.receivers == 0 {
+            ready!(Err(SendError(msg)));
+        }
+
+        let full = match this.chan.cap {
+            None => false,
+
+            // commit only when there is receiver waiting for it,
+            // so the message is never in the channel while we are still pending
+            Some(0) => !state.has_receiver_waiting(),
+
+            Some(cap) => state.queue.len() >= cap,
+        };
+
+        if full {
+            this.msg = Some(msg);
+            state.send_waiters.register(&mut this.key, cx.waker());
+            return Poll::Pending;
+        }
+
+        state.push(msg);
+        ready!(Ok(()));
+    }
+}
+
+impl<T> Drop for Send<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut state = self.chan.state.lock().unwrap();
+
+            // we already notified, pass it to the next waiter
+            if !state.send_waiters.remove(key) {
+                state.send_waiters.notify_one();
+            }
+        }
+    }
+}
+
+/// Future returned by [`Receiver::recv`]
+///
+/// [`Receiver::recv`]: struct.Receiver.html#method.recv
+pub struct Recv<'a, T> {
+    chan: &'a Chan<T>,
+    key: Option<usize>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.chan.state.lock().unwrap();
+
+        let val = match state.pop() {
+            Some(msg) => Ok(msg),
+            None if state.senders == 0 => Err(RecvError),
+            None => {
+                // notified, but the message is taken by other receiver
+                if let Some(key) = this.key {
+                    if !state.recv_waiters.contains(key) {
+                        state.release();
+                    }
+                }
+
+                state.recv_waiters.register(&mut this.key, cx.waker());
+
+                // let the waiting sender know that we are ready for its message
+                if this.chan.is_rendezvous() {
+                    state.send_waiters.notify_one();
+                }
+
+                return Poll::Pending;
+            }
+        };
+
+        if let Some(key) = this.key.take() {
+            if !state.recv_waiters.remove(key) {
+                state.release();
+            }
+        }
+
+        Poll::Ready(val)
+    }
+}
+
+impl<T> Drop for Recv<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut state = self.chan.state.lock().unwrap();
+
+            // we already notified, pass it to the next waiter
+            if state.recv_waiters.remove(key) || state.recv_waiters.notify_one() {
+                return;
+            }
+
+            state.release();
+
+            // nobody left to take the message that the sender committed to us,
+            // a rendezvous channel can't keep it, drop it outside the lock
+            // (the message may hold a sender)
+            if self.chan.is_rendezvous() && state.senders > 0 && state.queue.len() > state.reserved
+            {
+                let msg = state.queue.pop_back();
+                drop(state);
+                drop(msg);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn buffered() {
+        let (tx, rx) = bounded(2);
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+        assert!(matches!(tx.try_send(3), Err(TrySendError::Full(3))));
+        assert_eq!(tx.len(), 2);
+
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn disconnected() {
+        let (tx, rx) = unbounded();
+        tx.try_send(1).unwrap();
+        drop(tx);
+
+        // the remaining message can still be received
+        assert_eq!(rx.recv_blocking(), Ok(1));
+        assert_eq!(rx.recv_blocking(), Err(RecvError));
+
+        let (tx, rx) = bounded(0);
+        drop(rx);
+        assert!(matches!(tx.send_blocking(1), Err(SendError(1))));
+    }
+
+    #[test]
+    fn rendezvous_send() {
+        let (tx, rx) = bounded(0);
+
+        // nobody is waiting
+        assert!(matches!(tx.try_send(1), Err(TrySendError::Full(1))));
+
+        let handle = crate::spawn(async move {
+            let mut sum = 0;
+            while let Ok(i) = rx.recv().await {
+                sum += i;
+            }
+            sum
+        });
+
+        for i in 0..100 {
+            tx.send_blocking(i).unwrap();
+        }
+        drop(tx);
+
+        assert_eq!(crate::block_on(handle), (0..100).sum::<i32>());
+    }
+
+    #[test]
+    fn select_default() {
+        let (tx, rx) = bounded(0);
+
+        crate::block_on(async {
+            for _ in 0..100 {
+                crate::select! {
+                    _ = tx.send(1) => unreachable!("nobody is receiving"),
+                    default => {},
+                }
+            }
+        });
+
+        // the send is given back, nothing is left in the channel
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn select_only_one_branch() {
+        let (tx, rx) = bounded(0);
+
+        let received = Arc::new(AtomicUsize::new(0));
+        let handle = crate::spawn({
+            let received = received.clone();
+            async move {
+                while rx.recv().await.is_ok() {
+                    received.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+
+        let sent = crate::block_on(async {
+            let mut sent = 0;
+            for i in 0..1000 {
+                // the other branch is always ready, the receiver may or may not be waiting
+                crate::select! {
+                    res = tx.send(i) => {
+                        res.unwrap();
+                        sent += 1;
+                    },
+                    _ = future::ready(()) => {},
+                }
+                crate::yield_now().await;
+            }
+            sent
+        });
+
+        drop(tx);
+        crate::block_on(handle);
+
+        assert_eq!(received.load(Ordering::Relaxed), sent);
+    }
+
+    #[test]
+    fn select_rendezvous_recv() {
+        struct Msg(Arc<AtomicUsize>);
+
+        impl Drop for Msg {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let (tx, rx) = bounded(0);
+        let dropped = Arc::new(AtomicUsize::new(0));
+
+        crate::block_on(async {
+            for i in 0..100 {
+                // pending on the first poll, so the recv branch is already waiting
+                // when the message is sent, then the send branch is chosen
+                let mut polled = false;
+                let send = crate::chan::__poll_fn(|cx| {
+                    if !polled {
+                        polled = true;
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
+                    }
+                    assert!(tx.try_send(Msg(dropped.clone())).is_ok());
+                    Poll::Ready(())
+                });
+
+                crate::select! {
+                    _ = rx.recv() => unreachable!("the message is sent after the recv is polled"),
+                    _ = send => {},
+                }
+
+                // the message is dropped, not left in the channel
+                assert_eq!(rx.try_recv().err(), Some(TryRecvError::Empty));
+                assert_eq!(dropped.load(Ordering::Relaxed), i + 1);
+            }
+        });
+    }
+}
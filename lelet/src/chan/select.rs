@@ -0,0 +1,204 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Wait on multiple future, and run the branch of the first completed one
+///
+/// Just like `select` in golang, but it works for any future, not only channel operations.
+///
+/// ```ignore
+/// lelet::select! {
+///     msg = rx.recv() => println!("received {:?}", msg),
+///     res = tx.send(1) => println!("sent {:?}", res),
+///     _ = some_future => { println!("some_future is done") },
+///     default => println!("nothing is ready"),
+/// }
+/// ```
+///
+/// Every branch has the form `pattern = future => expression`, and separated by comma,
+/// the pattern must be irrefutable.
+///
+/// When more than one future is ready, one of them is chosen randomly,
+/// the other futures are dropped. Only the chosen branch take effect on the channels:
+/// a send that is not chosen never leave its message in the channel,
+/// even on rendezvous channel.
+///
+/// A receive on rendezvous channel that is not chosen may already have a message sent to it,
+/// the message is passed to the next waiting receiver, or dropped if there is none,
+/// it is never left in the channel.
+///
+/// The `default` branch is optional, it is executed when no future is ready.
+/// Without `default` branch, `select!` will wait until one of the future is completed,
+/// and `select! {}` will wait forever.
+///
+/// Can only be used inside async context, and supports up to 32 branches.
+#[macro_export]
+macro_rules! select {
+    ($($tokens:tt)*) => {
+        $crate::__select_internal!(
+            @parse
+            []
+            ()
+            [
+                (_0 0) (_1 1) (_2 2) (_3 3) (_4 4) (_5 5) (_6 6) (_7 7)
+                (_8 8) (_9 9) (_10 10) (_11 11) (_12 12) (_13 13) (_14 14) (_15 15)
+                (_16 16) (_17 17) (_18 18) (_19 19) (_20 20) (_21 21) (_22 22) (_23 23)
+                (_24 24) (_25 25) (_26 26) (_27 27) (_28 28) (_29 29) (_30 30) (_31 31)
+            ]
+            $($tokens)*
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __select_internal {
+    // done parsing
+    (@parse [$($branches:tt)*] $default:tt [$($ids:tt)*]) => {
+        $crate::__select_internal!(@emit [$($branches)*] $default)
+    };
+
+    // default branch
+    (@parse $branches:tt () $ids:tt default => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::__select_internal!(@parse $branches ($body) $ids $($($rest)*)?)
+    };
+
+    (@parse $branches:tt ($($default:tt)+) $ids:tt default $($rest:tt)*) => {
+        compile_error!("select! can only have one default branch")
+    };
+
+    (@parse $branches:tt $default:tt [] $($rest:tt)+) => {
+        compile_error!("select! supports up to 32 branches")
+    };
+
+    // future branch
+    (
+        @parse
+        [$($branches:tt)*]
+        $default:tt
+        [($id:ident $idx:tt) $($ids:tt)*]
+        $p:pat = $f:expr => $body:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::__select_internal!(
+            @parse
+            [$($branches)* [$id $idx ($p) ($f) ($body)]]
+            $default
+            [$($ids)*]
+            $($($rest)*)?
+        )
+    };
+
+    (@emit [$([$id:ident $idx:tt ($p:pat) ($f:expr) ($body:expr)])*] ($($default:expr)?)) => {{
+        #[allow(non_camel_case_types, dead_code)]
+        enum __Selected<$($id,)*> {
+            $($id($id),)*
+            __Default,
+        }
+
+        let __selected = {
+            // the futures are dropped in place at the end of this block,
+            // before running the selected branch
+            #[allow(unused_mut)]
+            let mut __futures = ($($f,)*);
+
+            let __count = [$(stringify!($id)),*].len();
+            let __start = $crate::chan::__random(__count);
+
+            #[allow(unreachable_code, unused_variables)]
+            $crate::chan::__poll_fn(|__cx| {
+                for __i in 0..__count {
+                    match (__start + __i) % __count {
+                        $(
+                            $idx => {
+                                // the futures are never moved
+                                let __f = unsafe {
+                                    ::std::pin::Pin::new_unchecked(&mut __futures.$idx)
+                                };
+                                if let ::std::task::Poll::Ready(__v) =
+                                    ::std::future::Future::poll(__f, __cx)
+                                {
+                                    return ::std::task::Poll::Ready(__Selected::$id(__v));
+                                }
+                            }
+                        )*
+                        _ => unreachable!(),
+                    }
+                }
+
+                $crate::__select_internal!(@pending $($default)?)
+            })
+            .await
+        };
+
+        match __selected {
+            $(__Selected::$id($p) => $body,)*
+            __Selected::__Default => $crate::__select_internal!(@default $($default)?),
+        }
+    }};
+
+    (@pending) => {
+        ::std::task::Poll::Pending
+    };
+
+    (@pending $default:expr) => {
+        ::std::task::Poll::Ready(__Selected::__Default)
+    };
+
+    (@default) => {
+        unreachable!()
+    };
+
+    (@default $default:expr) => {
+        $default
+    };
+}
+
+#[doc(hidden)]
+#[inline(always)]
+pub fn __poll_fn<T, F>(f: F) -> PollFn<F>
+where
+    F: FnMut(&mut Context) -> Poll<T>,
+{
+    PollFn(f)
+}
+
+#[doc(hidden)]
+pub struct PollFn<F>(F);
+
+impl<F> Unpin for PollFn<F> {}
+
+impl<T, F> Future for PollFn<F>
+where
+    F: FnMut(&mut Context) -> Poll<T>,
+{
+    type Output = T;
+
+    #[inline(always)]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        (self.0)(cx)
+    }
+}
+
+/// cheap random number in `0..n`, for picking the first branch to poll
+#[doc(hidden)]
+#[inline(always)]
+pub fn __random(n: usize) -> usize {
+    thread_local! {
+        static STATE: Cell<u32> = const { Cell::new(0x9e37_79b9) };
+    }
+
+    if n <= 1 {
+        return 0;
+    }
+
+    STATE.with(|state| {
+        // xorshift32
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        state.set(x);
+        x as usize % n
+    })
+}
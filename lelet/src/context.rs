@@ -0,0 +1,536 @@
+//! Cancellation, deadline and request-scoped values across tasks
+//!
+//! Just like `context` package in golang, a [`Context`] is derived from its parent,
+//! when a context is canceled, all the contexts derived from it are also canceled.
+//!
+//! Every task has a current context, retrievable via [`Context::current`],
+//! task spawned via [`spawn`] inherit the current context of the spawner.
+//!
+//! Cancellation is cooperative, the task need to check [`Context::err`]
+//! or wait on [`Context::done`] to know that it should stop.
+//!
+//! [`Context`]: struct.Context.html
+//! [`Context::current`]: struct.Context.html#method.current
+//! [`Context::err`]: struct.Context.html#method.err
+//! [`Context::done`]: struct.Context.html#method.done
+//! [`spawn`]: ../fn.spawn.html
+
+use std::any::Any;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{self, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+use crate::executor::{self, JoinHandle};
+use crate::time::{self, Sleep};
+use crate::waiters::Waiters;
+
+/// Carry cancellation signal, deadline, and values across tasks
+///
+/// Cloning a context is cheap, the clones refer to the same context.
+#[derive(Clone)]
+pub struct Context {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    parent: Option<Context>,
+    deadline: Option<Instant>,
+    value: Option<Box<dyn Any + Send + Sync>>,
+    state: Mutex<State>,
+}
+
+struct State {
+    err: Option<ContextError>,
+    children: Vec<Weak<Inner>>,
+    waiters: Waiters,
+
+    /// for context with its own deadline
+    timer: Option<Sleep>,
+}
+
+thread_local! {
+    static CURRENT: Cell<*const Context> = const { Cell::new(ptr::null()) };
+}
+
+impl Context {
+    /// Create new empty context, it is never canceled, has no deadline and no value
+    #[inline(always)]
+    pub fn background() -> Context {
+        Context::new(None, None, None)
+    }
+
+    /// The context of the current task,
+    /// or [`background`] if the current task has no context
+    ///
+    /// [`background`]: #method.background
+    #[inline(always)]
+    pub fn current() -> Context {
+        current().unwrap_or_else(Context::background)
+    }
+
+    /// Derive new context that can be canceled via the returned [`CancelFunc`]
+    ///
+    /// [`CancelFunc`]: struct.CancelFunc.html
+    #[inline(always)]
+    pub fn with_cancel(&self) -> (Context, CancelFunc) {
+        let ctx = self.child(self.deadline(), None);
+        (ctx.clone(), CancelFunc(ctx))
+    }
+
+    /// Derive new context that is canceled when `deadline` is reached,
+    /// or when the returned [`CancelFunc`] is called
+    ///
+    /// [`CancelFunc`]: struct.CancelFunc.html
+    #[inline(always)]
+    pub fn with_deadline(&self, deadline: Instant) -> (Context, CancelFunc) {
+        // the parent will be canceled sooner
+        if let Some(parent_deadline) = self.deadline() {
+            if parent_deadline <= deadline {
+                return self.with_cancel();
+            }
+        }
+
+        let ctx = self.child(Some(deadline), None);
+
+        let mut sleep = time::sleep_until(deadline);
+        let waker = Waker::from(Arc::new(DeadlineWaker(Arc::downgrade(&ctx.inner))));
+        match Pin::new(&mut sleep).poll(&mut task::Context::from_waker(&waker)) {
+            Poll::Ready(()) => ctx.cancel(ContextError::DeadlineExceeded),
+            Poll::Pending => {
+                let mut state = ctx.inner.state.lock().unwrap();
+                if state.err.is_none() {
+                    state.timer = Some(sleep);
+                }
+            }
+        }
+
+        (ctx.clone(), CancelFunc(ctx))
+    }
+
+    /// Derive new context that is canceled after `timeout`,
+    /// or when the returned [`CancelFunc`] is called
+    ///
+    /// [`CancelFunc`]: struct.CancelFunc.html
+    #[inline(always)]
+    pub fn with_timeout(&self, timeout: Duration) -> (Context, CancelFunc) {
+        self.with_deadline(time::deadline_after(timeout))
+    }
+
+    /// Derive new context that carry `value`
+    ///
+    /// Values are keyed by their type, use newtype to avoid collision,
+    /// and it shadow the value with the same type in the parent.
+    #[inline(always)]
+    pub fn with_value<T: Any + Send + Sync>(&self, value: T) -> Context {
+        self.child(self.deadline(), Some(Box::new(value)))
+    }
+
+    /// Get the value with type `T` from this context or its ancestors
+    #[inline(always)]
+    pub fn value<T: Any + Send + Sync>(&self) -> Option<&T> {
+        let mut inner: &Inner = &self.inner;
+        loop {
+            if let Some(value) = inner.value.as_ref().and_then(|v| v.downcast_ref()) {
+                return Some(value);
+            }
+            match inner.parent.as_ref() {
+                Some(parent) => inner = &parent.inner,
+                None => return None,
+            }
+        }
+    }
+
+    /// The instant when this context will be canceled, `None` if there is no deadline
+    #[inline(always)]
+    pub fn deadline(&self) -> Option<Instant> {
+        self.inner.deadline
+    }
+
+    /// Why this context is canceled, `None` if it is not canceled yet
+    #[inline(always)]
+    pub fn err(&self) -> Option<ContextError> {
+        self.inner.state.lock().unwrap().err
+    }
+
+    /// Wait until this context is canceled
+    #[inline(always)]
+    pub fn done(&self) -> Done<'_> {
+        Done {
+            ctx: self,
+            key: None,
+        }
+    }
+
+    /// Make this context as the current context while `future` is polled
+    #[inline(always)]
+    pub fn attach<F: Future>(&self, future: F) -> WithContext<F> {
+        WithContext {
+            ctx: self.clone(),
+            future,
+            tracked: executor::track(self),
+        }
+    }
+
+    /// Run the task in the background with new context derived from this context
+    ///
+    /// The new context is canceled when the task is canceled via [`JoinHandle::cancel`],
+    /// so the cancellation propagate to the tasks spawned by it.
+    ///
+    /// [`JoinHandle::cancel`]: ../struct.JoinHandle.html#method.cancel
+    #[inline(always)]
+    pub fn spawn<T, R>(&self, task: T) -> JoinHandle<R>
+    where
+        T: Future<Output = R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let ctx = self.child(self.deadline(), None);
+        executor::spawn_with_context(ctx, task)
+    }
+
+    #[inline(always)]
+    fn new(
+        parent: Option<Context>,
+        deadline: Option<Instant>,
+        value: Option<Box<dyn Any + Send + Sync>>,
+    ) -> Context {
+        Context {
+            inner: Arc::new(Inner {
+                parent,
+                deadline,
+                value,
+                state: Mutex::new(State {
+                    err: None,
+                    children: Vec::new(),
+                    waiters: Waiters::new(),
+                    timer: None,
+                }),
+            }),
+        }
+    }
+
+    #[inline(always)]
+    fn child(
+        &self,
+        deadline: Option<Instant>,
+        value: Option<Box<dyn Any + Send + Sync>>,
+    ) -> Context {
+        let child = Context::new(Some(self.clone()), deadline, value);
+
+        // background is never canceled, no need to track its children
+        if self.inner.parent.is_none() {
+            return child;
+        }
+
+        let mut state = self.inner.state.lock().unwrap();
+        match state.err {
+            Some(err) => {
+                drop(state);
+                child.cancel(err);
+            }
+            None => {
+                // clean up the dropped children before growing
+                if state.children.len() == state.children.capacity() {
+                    state.children.retain(|c| c.strong_count() > 0);
+                }
+                state.children.push(Arc::downgrade(&child.inner));
+            }
+        }
+
+        child
+    }
+
+    #[inline(always)]
+    pub(crate) fn cancel(&self, err: ContextError) {
+        self.inner.cancel(err);
+    }
+}
+
+impl Inner {
+    #[inline(always)]
+    fn cancel(&self, err: ContextError) {
+        let (children, timer) = {
+            let mut state = self.state.lock().unwrap();
+            if state.err.is_some() {
+                return;
+            }
+            state.err = Some(err);
+            state.waiters.notify_all();
+            (std::mem::take(&mut state.children), state.timer.take())
+        };
+
+        // deregister the timer outside the lock
+        drop(timer);
+
+        for child in children {
+            if let Some(child) = child.upgrade() {
+                child.cancel(err);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("deadline", &self.deadline())
+            .field("err", &self.err())
+            .finish()
+    }
+}
+
+/// the current context, `None` if there is no context
+#[inline(always)]
+pub(crate) fn current() -> Option<Context> {
+    CURRENT.with(|current| {
+        let ctx = current.get();
+        if ctx.is_null() {
+            None
+        } else {
+            Some(unsafe { &*ctx }.clone())
+        }
+    })
+}
+
+/// Cancel the context returned together with it
+///
+/// Dropping it does not cancel the context, call [`cancel`] when the work is done,
+/// so the resources (like the timer) are released as soon as possible.
+///
+/// [`cancel`]: #method.cancel
+pub struct CancelFunc(Context);
+
+impl CancelFunc {
+    /// Cancel the context and all the contexts derived from it,
+    /// calling it more than once is fine
+    #[inline(always)]
+    pub fn cancel(&self) {
+        self.0.cancel(ContextError::Canceled);
+    }
+}
+
+impl fmt::Debug for CancelFunc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("CancelFunc { .. }")
+    }
+}
+
+/// Why the context is canceled
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ContextError {
+    /// Canceled via [`CancelFunc`] or [`JoinHandle::cancel`]
+    ///
+    /// [`CancelFunc`]: struct.CancelFunc.html
+    /// [`JoinHandle::cancel`]: ../struct.JoinHandle.html#method.cancel
+    Canceled,
+
+    /// The deadline is reached
+    DeadlineExceeded,
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContextError::Canceled => f.write_str("context canceled"),
+            ContextError::DeadlineExceeded => f.write_str("context deadline exceeded"),
+        }
+    }
+}
+
+impl Error for ContextError {}
+
+/// Future returned by [`Context::done`]
+///
+/// [`Context::done`]: struct.Context.html#method.done
+pub struct Done<'a> {
+    ctx: &'a Context,
+    key: Option<usize>,
+}
+
+impl Future for Done<'_> {
+    type Output = ContextError;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<ContextError> {
+        let this = &mut *self;
+        let mut state = this.ctx.inner.state.lock().unwrap();
+
+        match state.err {
+            Some(err) => {
+                this.key = None;
+                Poll::Ready(err)
+            }
+            None => {
+                state.waiters.register(&mut this.key, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Done<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.ctx.inner.state.lock().unwrap().waiters.remove(key);
+        }
+    }
+}
+
+/// Future returned by [`Context::attach`]
+///
+/// [`Context::attach`]: struct.Context.html#method.attach
+pub struct WithContext<F> {
+    ctx: Context,
+    future: F,
+
+    /// counted by [`run_with`] until it is done
+    ///
+    /// [`run_with`]: ../fn.run_with.html
+    tracked: Option<executor::Tracked>,
+}
+
+impl<F: Future> Future for WithContext<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<F::Output> {
+        // the future is never moved
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let _guard = Enter(CURRENT.with(|current| current.replace(&this.ctx)));
+        let poll = unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx);
+
+        // don't wait for the future to be dropped
+        if poll.is_ready() {
+            this.tracked.take();
+        }
+
+        poll
+    }
+}
+
+impl<F> fmt::Debug for WithContext<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WithContext")
+            .field("ctx", &self.ctx)
+            .finish()
+    }
+}
+
+/// restore the previous current context when dropped
+struct Enter(*const Context);
+
+impl Drop for Enter {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.0));
+    }
+}
+
+/// cancel the context when the deadline is reached
+struct DeadlineWaker(Weak<Inner>);
+
+impl Wake for DeadlineWaker {
+    fn wake(self: Arc<Self>) {
+        if let Some(inner) = self.0.upgrade() {
+            inner.cancel(ContextError::DeadlineExceeded);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel() {
+        let (parent, cancel) = Context::background().with_cancel();
+        let child = parent.with_value(1u32);
+        let (grandchild, _) = child.with_cancel();
+
+        assert_eq!(grandchild.err(), None);
+        cancel.cancel();
+        assert_eq!(parent.err(), Some(ContextError::Canceled));
+        assert_eq!(grandchild.err(), Some(ContextError::Canceled));
+        executor::block_on(grandchild.done());
+
+        // derived from canceled context
+        let (ctx, _) = parent.with_timeout(Duration::from_secs(60));
+        assert_eq!(ctx.err(), Some(ContextError::Canceled));
+
+        // canceling the child doesn't affect the parent
+        let (parent, _) = Context::background().with_cancel();
+        let (child, cancel) = parent.with_cancel();
+        cancel.cancel();
+        assert_eq!(child.err(), Some(ContextError::Canceled));
+        assert_eq!(parent.err(), None);
+    }
+
+    #[test]
+    fn deadline() {
+        let (ctx, _) = Context::background().with_timeout(Duration::from_millis(50));
+        assert_eq!(ctx.err(), None);
+        executor::block_on(ctx.done());
+        assert_eq!(ctx.err(), Some(ContextError::DeadlineExceeded));
+        assert!(Instant::now() >= ctx.deadline().unwrap());
+
+        // the earlier deadline of the parent is inherited
+        let (parent, _) = Context::background().with_timeout(Duration::from_secs(1));
+        let (child, _) = parent.with_timeout(Duration::from_secs(60));
+        assert_eq!(child.deadline(), parent.deadline());
+        assert_eq!(parent.with_value(()).deadline(), parent.deadline());
+
+        // already passed
+        let (ctx, _) = Context::background().with_deadline(Instant::now());
+        assert_eq!(ctx.err(), Some(ContextError::DeadlineExceeded));
+    }
+
+    #[test]
+    fn value() {
+        #[derive(Debug, PartialEq)]
+        struct Name(&'static str);
+
+        let ctx = Context::background().with_value(Name("a")).with_value(1u32);
+        let (ctx, _) = ctx.with_cancel();
+        assert_eq!(ctx.value::<Name>(), Some(&Name("a")));
+        assert_eq!(ctx.value::<u32>(), Some(&1));
+        assert_eq!(ctx.value::<u64>(), None);
+
+        // shadowed
+        let child = ctx.with_value(Name("b"));
+        assert_eq!(child.value::<Name>(), Some(&Name("b")));
+        assert_eq!(ctx.value::<Name>(), Some(&Name("a")));
+
+        // inherited by the spawned task
+        let handle = child.spawn(async {
+            let ctx = Context::current();
+            (
+                ctx.value::<Name>().map(|n| n.0),
+                ctx.value::<u32>().copied(),
+            )
+        });
+        assert_eq!(executor::block_on(handle), (Some("b"), Some(1)));
+    }
+
+    #[test]
+    fn cancel_task() {
+        let (tx, rx) = crate::chan::bounded(1);
+
+        let handle = Context::background().spawn(async move {
+            // inherit the context of this task
+            let inner = executor::spawn(async {
+                let ctx = Context::current();
+                ctx.done().await;
+                ctx.err()
+            });
+            tx.send(inner).await.unwrap();
+            crate::time::sleep(Duration::from_secs(60)).await;
+        });
+
+        let inner = rx.recv_blocking().unwrap();
+        handle.cancel();
+        assert_eq!(executor::block_on(inner), Some(ContextError::Canceled));
+    }
+}
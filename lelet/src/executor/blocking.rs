@@ -0,0 +1,129 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use super::machine;
+use super::processor::Processor;
+
+/// Run blocking code inside a task
+///
+/// Just like `entersyscall` and `exitsyscall` in golang,
+/// the processor is handed off to a new machine as soon as there is other task to run
+/// while `f` is running, so they don't wait for the blocking detection,
+/// and the current thread try to take the processor back after `f` return.
+///
+/// No new machine is spawned if nothing need the processor in the meantime,
+/// so it is cheap to use it for call that is only blocking sometimes.
+///
+/// Outside of a task, it just call `f`.
+///
+/// See [`BlockingGuard`] if the blocking code is not in a single closure.
+///
+/// [`BlockingGuard`]: struct.BlockingGuard.html
+#[inline(always)]
+pub fn blocking<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let _guard = BlockingGuard::enter();
+    f()
+}
+
+/// Guard for blocking section, returned by [`BlockingGuard::enter`]
+///
+/// The blocking section end when it is dropped, see [`blocking`].
+///
+/// [`BlockingGuard::enter`]: #method.enter
+/// [`blocking`]: fn.blocking.html
+pub struct BlockingGuard {
+    /// the processor to take back, `None` if it is not needed
+    processor: Option<&'static Processor>,
+
+    // !Send + !Sync, must be dropped in the same thread
+    _marker: PhantomData<*mut ()>,
+}
+
+impl BlockingGuard {
+    /// Start blocking section
+    ///
+    /// Do nothing when it is called outside of a task,
+    /// or when the current thread is already in blocking section.
+    #[inline(always)]
+    pub fn enter() -> BlockingGuard {
+        BlockingGuard {
+            processor: machine::enter_blocking(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Drop for BlockingGuard {
+    fn drop(&mut self) {
+        if let Some(p) = self.processor.take() {
+            machine::exit_blocking(p);
+        }
+    }
+}
+
+impl fmt::Debug for BlockingGuard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BlockingGuard")
+            .field("active", &self.processor.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn is_active(guard: &BlockingGuard) -> bool {
+        guard.processor.is_some()
+    }
+
+    #[test]
+    fn outside_task() {
+        let guard = BlockingGuard::enter();
+        assert!(!is_active(&guard));
+        assert_eq!(format!("{:?}", guard), "BlockingGuard { active: false }");
+
+        assert_eq!(blocking(|| 5), 5);
+    }
+
+    #[test]
+    fn nested() {
+        crate::block_on(crate::spawn(async {
+            let outer = BlockingGuard::enter();
+            assert!(is_active(&outer));
+
+            // the outer one take back the processor
+            let inner = BlockingGuard::enter();
+            assert!(!is_active(&inner));
+            assert!(blocking(|| !is_active(&BlockingGuard::enter())));
+            drop(inner);
+            drop(outer);
+        }));
+
+        // the section is ended, the processor can block again
+        assert!(crate::block_on(crate::spawn(async {
+            is_active(&BlockingGuard::enter())
+        })));
+    }
+
+    #[test]
+    fn run_others() {
+        for _ in 0..10 {
+            crate::block_on(crate::spawn(async {
+                blocking(|| {
+                    // it can only run when the processor is handed off,
+                    // when the executor only have one
+                    let (tx, rx) = mpsc::channel();
+                    crate::spawn(async move { tx.send(()).unwrap() });
+                    rx.recv_timeout(Duration::from_secs(10)).unwrap();
+                })
+            }));
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// bucket `i` count the duration in `[2^(i-1), 2^i)` nanoseconds
+const BUCKETS: usize = 40;
+
+/// all the buckets are halved when the number of samples reach this,
+/// so old samples fade out
+const WINDOW: u32 = 4096;
+
+/// Histogram of poll duration of a processor
+///
+/// Only the machine that currently own the processor can record,
+/// others (sysmon) can only read.
+pub struct Histogram {
+    buckets: [AtomicU32; BUCKETS],
+    count: AtomicU32,
+}
+
+impl Histogram {
+    #[inline(always)]
+    pub fn new() -> Histogram {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU32 = AtomicU32::new(0);
+        Histogram {
+            buckets: [ZERO; BUCKETS],
+            count: AtomicU32::new(0),
+        }
+    }
+
+    /// owner only
+    #[inline(always)]
+    pub fn record(&self, duration: Duration) {
+        let ns = std::cmp::min(duration.as_nanos(), u64::MAX as u128) as u64;
+        let i = std::cmp::min(64 - ns.leading_zeros() as usize, BUCKETS - 1);
+
+        let bucket = &self.buckets[i];
+        bucket.store(bucket.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+
+        let count = self.count.load(Ordering::Relaxed) + 1;
+        if count < WINDOW {
+            self.count.store(count, Ordering::Relaxed);
+            return;
+        }
+
+        let mut count = 0;
+        for bucket in self.buckets.iter() {
+            let n = bucket.load(Ordering::Relaxed) / 2;
+            bucket.store(n, Ordering::Relaxed);
+            count += n;
+        }
+        self.count.store(count, Ordering::Relaxed);
+    }
+
+    /// the upper bound of the duration that `percent` of the samples are below it,
+    /// `None` if there is no sample yet
+    #[inline(always)]
+    pub fn percentile(&self, percent: u32) -> Option<Duration> {
+        let mut counts = [0u32; BUCKETS];
+        let mut total: u64 = 0;
+        for (c, bucket) in counts.iter_mut().zip(self.buckets.iter()) {
+            *c = bucket.load(Ordering::Relaxed);
+            total += *c as u64;
+        }
+
+        if total == 0 {
+            return None;
+        }
+
+        let target = (total * percent as u64).div_ceil(100);
+        let mut seen = 0;
+        for (i, &c) in counts.iter().enumerate() {
+            seen += c as u64;
+            if seen >= target {
+                return Some(Duration::from_nanos(1 << i));
+            }
+        }
+
+        Some(Duration::from_nanos(1 << (BUCKETS - 1)))
+    }
+}
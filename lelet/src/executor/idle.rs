@@ -0,0 +1,119 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::system;
+
+/// Wait until the executor is idle
+///
+/// The executor is idle when there is no task in the queues of every processor,
+/// and no task is running, e.g. all the tasks are done or waiting for something (timer, channel, etc).
+///
+/// When it is awaited inside a task, that task is not counted,
+/// because it is not running while waiting.
+///
+/// Useful in tests and batch jobs, instead of sleeping for arbitrary duration.
+///
+/// A blocking task is still counted after its processor is handed off to a new machine,
+/// but tasks that run in the thread pool (see [`set_chronic_blocking_threshold`]) are not.
+///
+/// [`set_chronic_blocking_threshold`]: fn.set_chronic_blocking_threshold.html
+#[inline(always)]
+pub fn wait_idle() -> WaitIdle {
+    WaitIdle {
+        epoch: None,
+        key: None,
+    }
+}
+
+/// Block current thread until the executor is idle
+///
+/// See [`wait_idle`]
+///
+/// [`wait_idle`]: fn.wait_idle.html
+#[inline(always)]
+pub fn block_until_idle() {
+    super::block_on(wait_idle());
+}
+
+/// Future returned by [`wait_idle`]
+///
+/// [`wait_idle`]: fn.wait_idle.html
+pub struct WaitIdle {
+    epoch: Option<u64>,
+    key: Option<usize>,
+}
+
+impl Future for WaitIdle {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = &mut *self;
+        if system::get().poll_idle(&mut this.epoch, &mut this.key, cx.waker()) {
+            if let Some(key) = this.key.take() {
+                system::get().remove_idle_waiter(key);
+            }
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for WaitIdle {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            system::get().remove_idle_waiter(key);
+        }
+    }
+}
+
+impl fmt::Debug for WaitIdle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WaitIdle").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn blocked_task() {
+        let done = Arc::new(AtomicBool::new(false));
+
+        let d = done.clone();
+        crate::spawn(async move {
+            thread::sleep(Duration::from_millis(500));
+            d.store(true, Ordering::SeqCst);
+        });
+
+        // so the processor is handed off, the blocked task is left on its own machine
+        crate::spawn(async {});
+
+        block_until_idle();
+        assert!(done.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn detached_task() {
+        // come back before or after the new machine take over,
+        // the blocked machine is not counted anymore either way
+        for i in 0..500 {
+            crate::spawn(async move {
+                crate::detach_current_thread();
+                if i % 2 == 0 {
+                    thread::sleep(Duration::from_millis(10));
+                }
+            });
+        }
+
+        block_until_idle();
+    }
+}
@@ -1,9 +1,11 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::marker::PhantomData;
 use std::rc::Rc;
 
+use std::sync::atomic::{self, Ordering};
+
 #[cfg(feature = "tracing")]
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::AtomicUsize;
 
 #[cfg(feature = "tracing")]
 use log::trace;
@@ -13,6 +15,8 @@ use lelet_utils::abort_on_panic;
 use crate::thread_pool;
 
 use super::processor::Processor;
+use super::system;
+use super::task::TaskTag;
 use super::Task;
 
 /// Machine is the one who have OS thread
@@ -20,14 +24,23 @@ pub struct Machine {
     #[cfg(feature = "tracing")]
     pub id: usize,
 
-    processor: &'static Processor,
+    /// can change when the old one is stolen, see [`Processor::claim`]
+    ///
+    /// [`Processor::claim`]: ../processor/struct.Processor.html#method.claim
+    processor: Cell<&'static Processor>,
+
+    /// schedtick of the processor when the last blocking section started,
+    /// see [`enter_blocking`]
+    ///
+    /// [`enter_blocking`]: fn.enter_blocking.html
+    blocking_at: Cell<u64>,
 
     // !Send + !Sync
     _marker: PhantomData<*mut ()>,
 }
 
 thread_local! {
-    static CURRENT: RefCell<Option<Rc<Machine>>> = RefCell::new(None);
+    static CURRENT: RefCell<Option<Rc<Machine>>> = const { RefCell::new(None) };
 }
 
 impl Machine {
@@ -40,7 +53,8 @@ impl Machine {
             #[cfg(feature = "tracing")]
             id: MACHINE_ID_COUNTER.fetch_add(1, Ordering::Relaxed),
 
-            processor,
+            processor: Cell::new(processor),
+            blocking_at: Cell::new(0),
 
             _marker: PhantomData,
         };
@@ -51,23 +65,55 @@ impl Machine {
         Rc::new(machine)
     }
 
+    /// a blocking section was entered in the poll that started at `schedtick`
     #[inline(always)]
-    fn run(self: &Rc<Machine>) {
-        CURRENT.with(|current| {
-            let old = current.borrow_mut().replace(self.clone());
+    pub fn is_blocking_at(&self, schedtick: u64) -> bool {
+        self.blocking_at.get() == schedtick
+    }
 
-            // just to make sure that the machine is not cached in thread pool
-            assert!(old.is_none());
-        });
+    #[inline(always)]
+    fn run(self: &Rc<Machine>, handoff: bool) {
+        // the blocked machine came back before us
+        if handoff && !self.processor.get().claim() {
+            #[cfg(feature = "tracing")]
+            trace!(
+                "{:?} is already reclaimed, {:?} is not needed",
+                self.processor.get(),
+                self
+            );
+
+            return;
+        }
 
         #[cfg(feature = "tracing")]
         crate::thread_pool::THREAD_ID.with(|tid| {
             trace!("{:?} is running on {:?}", self, tid);
         });
 
-        self.processor.run_on(self);
+        loop {
+            CURRENT.with(|current| {
+                let old = current.borrow_mut().replace(self.clone());
 
-        CURRENT.with(|current| current.borrow_mut().take());
+                // just to make sure that the machine is not cached in thread pool
+                assert!(old.is_none());
+            });
+
+            self.processor.get().run_on(self);
+
+            CURRENT.with(|current| current.borrow_mut().take());
+
+            // our processor is stolen because we were blocked,
+            // take the one that is waiting for new machine, if any
+            match system::get().processors_claim() {
+                Some(p) => {
+                    #[cfg(feature = "tracing")]
+                    trace!("{:?} is claimed by {:?}", p, self);
+
+                    self.processor.set(p);
+                }
+                None => break,
+            }
+        }
     }
 }
 
@@ -89,19 +135,107 @@ impl Drop for Machine {
 pub fn spawn(processor: &'static Processor) {
     thread_pool::spawn_box(Box::new(move || {
         abort_on_panic(move || {
-            Machine::new(processor).run();
+            Machine::new(processor).run(false);
+        })
+    }));
+}
+
+/// spawn new machine to take over the processor from the blocked machine,
+/// unless the blocked machine come back before the new machine is running
+///
+/// return false if it is already handed off, or there are too many blocked machines
+#[inline(always)]
+pub fn handoff(processor: &'static Processor) -> bool {
+    // sysmon check it again until the new machine take over,
+    // don't count it as refused by the limit
+    if processor.is_handed_off() {
+        return false;
+    }
+    if !system::add_blocked_machine() {
+        #[cfg(feature = "tracing")]
+        trace!(
+            "Too many blocked machines, {:?} is not handed off",
+            processor
+        );
+
+        return false;
+    }
+    if !processor.start_handoff() {
+        system::remove_blocked_machine();
+        return false;
+    }
+    thread_pool::spawn_box(Box::new(move || {
+        abort_on_panic(move || {
+            Machine::new(processor).run(true);
         })
     }));
+    true
 }
 
+/// mark the processor of the current machine as blocking if it is running a task,
+/// it is handed off as soon as there is other task to run,
+/// return the processor so it can be reclaimed via [`exit_blocking`]
+///
+/// [`exit_blocking`]: fn.exit_blocking.html
 #[inline(always)]
-pub fn direct_push(task: Task) -> Result<(), Task> {
+pub fn enter_blocking() -> Option<&'static Processor> {
+    CURRENT.with(|current| {
+        let current = current.borrow();
+        let m = current.as_ref()?;
+        let p = m.processor.get();
+
+        // not running a task, or the processor is already stolen
+        if p.current_task(m).is_null() {
+            return None;
+        }
+
+        // already in blocking section
+        if !p.start_blocking() {
+            return None;
+        }
+
+        m.blocking_at.set(p.schedtick());
+
+        #[cfg(feature = "tracing")]
+        trace!("{:?} is entering blocking section on {:?}", m, p);
+
+        // pair with the fence in System::notify,
+        // either we see the new task, or the pusher see that we are blocking
+        atomic::fence(Ordering::SeqCst);
+
+        // others are waiting, don't make them wait for us
+        if !p.is_empty() {
+            handoff(p);
+        }
+
+        Some(p)
+    })
+}
+
+/// end the blocking section started by [`enter_blocking`],
+/// take back the processor if it is handed off
+///
+/// [`enter_blocking`]: fn.enter_blocking.html
+#[inline(always)]
+pub fn exit_blocking(p: &'static Processor) {
+    CURRENT.with(|current| {
+        if let Some(m) = current.borrow().as_ref() {
+            if p.reclaim(m) {
+                #[cfg(feature = "tracing")]
+                trace!("{:?} is reclaimed by {:?} after blocking section", p, m);
+            }
+        }
+    })
+}
+
+#[inline(always)]
+pub fn direct_push(task: Task, next: bool) -> Result<&'static Processor, Task> {
     CURRENT.with(|current| {
         let mut current = current.borrow_mut();
         match current.as_ref() {
             None => Err(task),
-            Some(m) => match m.processor.push_local(m, task) {
-                Ok(()) => Ok(()),
+            Some(m) => match m.processor.get().push_local(m, task, next) {
+                Ok(()) => Ok(m.processor.get()),
                 Err(err) => {
                     current.take();
                     Err(err)
@@ -111,18 +245,93 @@ pub fn direct_push(task: Task) -> Result<(), Task> {
     })
 }
 
+/// call `f` (the poll of the current task),
+/// and record it in the task if the processor is handed off because of it
+#[inline(always)]
+pub fn observe_poll<R>(f: impl FnOnce() -> R) -> R {
+    let running = CURRENT.with(|current| {
+        let current = current.borrow();
+        let m = current.as_ref()?;
+        let p = m.processor.get();
+        let task = p.current_task(m);
+        if task.is_null() {
+            None
+        } else {
+            Some((p, p.schedtick(), task))
+        }
+    });
+
+    let result = f();
+
+    if let Some((p, schedtick, task)) = running {
+        let blocked = CURRENT.with(|current| match current.borrow().as_ref() {
+            // the processor is lost, see direct_push
+            None => false,
+
+            // blocking section is expected, not an incident
+            Some(m) => !m.is_blocking_at(schedtick) && p.is_handed_off_from(m),
+        });
+
+        if blocked {
+            // still safe, we are inside the poll of the task
+            unsafe { &*task }.add_blocking_incident();
+        }
+    }
+
+    result
+}
+
+/// call `f` as if this thread is not a machine,
+/// so the tasks woken by `f` are not pushed to the local queue
+#[inline(always)]
+pub fn detached<R>(f: impl FnOnce() -> R) -> R {
+    let m = CURRENT.with(|current| current.borrow_mut().take());
+    let result = f();
+    CURRENT.with(|current| *current.borrow_mut() = m);
+    result
+}
+
+/// call `f` with the task that currently running on this thread, if any
+#[inline(always)]
+pub fn with_current_task(f: impl FnOnce(&TaskTag)) {
+    CURRENT.with(|current| {
+        if let Some(m) = current.borrow().as_ref() {
+            let task = m.processor.get().current_task(m);
+            if !task.is_null() {
+                f(unsafe { &*task });
+            }
+        }
+    })
+}
+
+/// hand off the processor of the current machine right away, see [`detach_current_thread`]
+///
+/// [`detach_current_thread`]: ../system/fn.detach_current_thread.html
 #[inline(always)]
 pub fn respawn() {
     CURRENT.with(|current| {
-        if let Some(m) = current.borrow_mut().take() {
-            #[cfg(feature = "tracing")]
-            trace!(
-                "{:?} is giving up on {:?}, spawn new machine",
-                m,
-                m.processor
-            );
+        let current = current.borrow();
+        let m = match current.as_ref() {
+            Some(m) => m,
+            None => return,
+        };
+        let p = m.processor.get();
 
-            spawn(m.processor)
+        // not running a task, or the processor is already stolen
+        if p.current_task(m).is_null() {
+            return;
+        }
+
+        // requested, not a blocking incident
+        m.blocking_at.set(p.schedtick());
+
+        // just like the blocking detection, if we come back before the new machine run,
+        // we take back the processor via end_handoff, and the new machine is not needed,
+        // otherwise we leave when we fail to take back the processor.
+        // keep blocking the processor if there are too many blocked machines
+        if handoff(p) {
+            #[cfg(feature = "tracing")]
+            trace!("{:?} is giving up on {:?}, spawn new machine", m, p);
         }
     })
 }
@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use super::system;
+
+/// Snapshot of the executor counters, returned by [`metrics`]
+///
+/// All counters start from zero when the program start.
+///
+/// [`metrics`]: fn.metrics.html
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Metrics {
+    /// number of times sysmon spawn new machine because a task is blocking
+    pub blocking_incidents: u64,
+
+    /// number of tasks that is moved to the thread pool because they keep blocking,
+    /// see [`set_chronic_blocking_threshold`]
+    ///
+    /// [`set_chronic_blocking_threshold`]: fn.set_chronic_blocking_threshold.html
+    pub chronic_blocking_tasks: u64,
+
+    /// number of machines that is currently blocked, see [`set_max_blocked_machines`]
+    ///
+    /// [`set_max_blocked_machines`]: fn.set_max_blocked_machines.html
+    pub blocked_machines: usize,
+
+    /// number of times new machine is not spawned because of [`set_max_blocked_machines`]
+    ///
+    /// [`set_max_blocked_machines`]: fn.set_max_blocked_machines.html
+    pub blocked_machines_limit_hits: u64,
+
+    /// one for each processor
+    pub processors: Vec<ProcessorMetrics>,
+}
+
+/// Part of [`Metrics`]
+///
+/// [`Metrics`]: struct.Metrics.html
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct ProcessorMetrics {
+    /// current blocking threshold, see [`set_blocking_threshold`]
+    ///
+    /// [`set_blocking_threshold`]: fn.set_blocking_threshold.html
+    pub blocking_threshold: Duration,
+
+    /// median of recent poll duration (rounded up to power of two nanoseconds),
+    /// only measured in adaptive blocking threshold mode
+    pub poll_p50: Option<Duration>,
+
+    /// same as `poll_p50`, but for 99th percentile
+    pub poll_p99: Option<Duration>,
+}
+
+/// Get the current value of the executor counters
+#[inline(always)]
+pub fn metrics() -> Metrics {
+    Metrics {
+        blocking_incidents: COUNTERS.blocking_incidents.load(Ordering::Relaxed),
+        chronic_blocking_tasks: COUNTERS.chronic_blocking_tasks.load(Ordering::Relaxed),
+        blocked_machines: COUNTERS.blocked_machines.load(Ordering::Relaxed),
+        blocked_machines_limit_hits: COUNTERS.blocked_machines_limit_hits.load(Ordering::Relaxed),
+        processors: system::get()
+            .processors()
+            .iter()
+            .map(|p| ProcessorMetrics {
+                blocking_threshold: system::blocking_threshold(p),
+                poll_p50: p.poll_percentile(50),
+                poll_p99: p.poll_percentile(99),
+            })
+            .collect(),
+    }
+}
+
+pub struct Counters {
+    pub blocking_incidents: AtomicU64,
+    pub chronic_blocking_tasks: AtomicU64,
+    pub blocked_machines: AtomicUsize,
+    pub blocked_machines_limit_hits: AtomicU64,
+}
+
+pub static COUNTERS: Counters = Counters {
+    blocking_incidents: AtomicU64::new(0),
+    chronic_blocking_tasks: AtomicU64::new(0),
+    blocked_machines: AtomicUsize::new(0),
+    blocked_machines_limit_hits: AtomicU64::new(0),
+};
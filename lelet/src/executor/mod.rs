@@ -10,42 +10,291 @@
 //    it must exit as soon as possible
 // 3. each processor have dedicated global queue
 
+mod blocking;
+mod histogram;
+mod idle;
 mod machine;
+mod metrics;
 mod processor;
+mod run;
+mod runq;
+mod scope;
 mod system;
 mod task;
+mod task_group;
 
 pub use system::get_num_cpus;
+pub use system::set_blocking_threshold;
+pub use system::set_chronic_blocking_threshold;
+pub use system::set_max_blocked_machines;
+pub use system::set_measure_poll_time;
 pub use system::set_num_cpus;
+pub use system::set_sysmon_interval;
+pub use system::{set_max_spinning, set_spin_duration};
 
 pub use system::detach_current_thread;
 
+pub use blocking::{blocking, BlockingGuard};
+
+pub use idle::{block_until_idle, wait_idle, WaitIdle};
+
+pub use run::{run, run_with, ExitPolicy};
+
+pub(crate) use run::{track, Tracked};
+
+pub use task::TaskStats;
+
+pub use metrics::{metrics, Metrics, ProcessorMetrics};
+
+pub use scope::{scope, Scope};
+
+pub use task_group::{JoinNext, TaskGroup};
+
 use std::future::Future;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::ptr;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use self::task::{TaskFuture, TaskTag};
 
-use self::task::TaskTag;
+use crate::context::{self, ContextError};
+use crate::time::Driver;
 
 type Task = async_task::Task<TaskTag>;
 
 /// Run the task in the background.
 ///
-/// Just like goroutine in golang, there is no way to cancel a task,
-/// but unlike goroutine you can `await` the task
+/// Just like goroutine in golang, but unlike goroutine you can `await` the task,
+/// or cancel it via [`JoinHandle::cancel`]
 ///
 /// # Panic
 ///
 /// When a task panic, it will abort the entire program
+///
+/// [`JoinHandle::cancel`]: struct.JoinHandle.html#method.cancel
 #[inline(always)]
 pub fn spawn<T, R>(task: T) -> JoinHandle<R>
+where
+    T: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    spawn_inherit(task, TaskTag::new(None))
+}
+
+/// Same as [`spawn`], but give back the task when there are too many blocked machines
+///
+/// Useful to slow down the producer of blocking tasks, see [`set_max_blocked_machines`].
+///
+/// [`spawn`]: fn.spawn.html
+/// [`set_max_blocked_machines`]: fn.set_max_blocked_machines.html
+#[inline(always)]
+pub fn try_spawn<T, R>(task: T) -> Result<JoinHandle<R>, T>
+where
+    T: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    if system::is_blocked_machines_full() {
+        return Err(task);
+    }
+    Ok(spawn(task))
+}
+
+/// Run the task in the background, and pin it to processor number `index`
+///
+/// The task will only run on that processor, it will never be stolen by other processors,
+/// useful to keep related tasks on the same core for cache locality.
+///
+/// # Panic
+///
+/// When `index` is not less than [`get_num_cpus`]
+///
+/// [`get_num_cpus`]: fn.get_num_cpus.html
+#[inline(always)]
+pub fn spawn_on<T, R>(index: usize, task: T) -> JoinHandle<R>
+where
+    T: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    let mut tag = TaskTag::new(None);
+    tag.pin_to(system::get().processor(index));
+    spawn_inherit(task, tag)
+}
+
+/// Run the task in the background, starting on processor number `index`
+///
+/// Unlike [`spawn_on`], this is only a hint, the task can still be stolen
+/// by other processors when they are idle.
+///
+/// # Panic
+///
+/// When `index` is not less than [`get_num_cpus`]
+///
+/// [`spawn_on`]: fn.spawn_on.html
+/// [`get_num_cpus`]: fn.get_num_cpus.html
+#[inline(always)]
+pub fn spawn_near<T, R>(index: usize, task: T) -> JoinHandle<R>
+where
+    T: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    let tag = TaskTag::new(None);
+    tag.set_processor_hint(system::get().processor(index));
+
+    // the first push go to the global queue of the hinted processor
+    tag.set_yield_to_global();
+
+    spawn_inherit(task, tag)
+}
+
+/// Run all the tasks in the background
+///
+/// Same as calling [`spawn`] for each task, but the sleeping processors
+/// are only notified once after all the tasks are pushed,
+/// cheaper when spawning a lot of tasks at once.
+///
+/// Unlike [`spawn`], the tasks don't run next,
+/// they are put in the back of the queue in order.
+///
+/// [`spawn`]: fn.spawn.html
+#[inline(always)]
+pub fn spawn_batch<I, T, R>(tasks: I) -> Vec<JoinHandle<R>>
+where
+    I: IntoIterator<Item = T>,
+    T: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    let ctx = context::current();
+    let tasks = tasks.into_iter();
+    let mut handles = Vec::with_capacity(tasks.size_hint().0);
+
+    // push as soon as it is created, while it is still hot in the cache
+    system::get().push_batch(tasks.map(|task| {
+        let (task, handle) = match &ctx {
+            Some(ctx) => create(ctx.attach(task), TaskTag::new(None)),
+            None => create(task, TaskTag::new(None)),
+        };
+        handles.push(handle);
+        task
+    }));
+
+    handles
+}
+
+/// spawn the task with the current context
+#[inline(always)]
+fn spawn_inherit<T, R>(task: T, tag: TaskTag) -> JoinHandle<R>
+where
+    T: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    match context::current() {
+        Some(ctx) => spawn_tagged(ctx.attach(task), tag),
+        None => spawn_tagged(task, tag),
+    }
+}
+
+/// spawn the task with its own context, the context is canceled when the task is canceled
+#[inline(always)]
+pub(crate) fn spawn_with_context<T, R>(ctx: context::Context, task: T) -> JoinHandle<R>
+where
+    T: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    spawn_tagged(ctx.attach(task), TaskTag::new(Some(ctx)))
+}
+
+#[inline(always)]
+fn spawn_tagged<T, R>(task: T, tag: TaskTag) -> JoinHandle<R>
+where
+    T: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    let (task, handle) = create(task, tag);
+
+    // not via schedule, it is not counted as wake,
+    // a new task run next, just like `go` statement in golang
+    system::get().push(task, true);
+
+    handle
+}
+
+/// create the task without scheduling it
+#[inline(always)]
+fn create<T, R>(task: T, tag: TaskTag) -> (Task, JoinHandle<R>)
 where
     T: Future<Output = R> + Send + 'static,
     R: Send + 'static,
 {
     let system = system::get();
-    let (task, handle) = async_task::spawn(task, move |task| system.push(task), TaskTag::new());
-    task.schedule();
-    JoinHandle(handle)
+    let task = TaskFuture(task);
+    let (task, handle) = async_task::spawn(
+        task,
+        move |task| {
+            task.tag().add_wake();
+
+            // put in the back of the queue, so a task that awaits its children
+            // is not polled again for each child that is done
+            system.push(task, false)
+        },
+        tag,
+    );
+    (task, JoinHandle(handle))
+}
+
+/// Yield the current task, so the other tasks get a chance to run
+///
+/// The task is put at the back of the local queue of the current processor,
+/// so all the tasks in that queue run before it is polled again,
+/// and so does the task that was just spawned there, it always run next.
+#[inline(always)]
+pub async fn yield_now() {
+    lelet_utils::Yields(1).await;
+}
+
+/// Yield the current task to the global queue, like `runtime.Gosched` in golang
+///
+/// Stronger than [`yield_now`], the task is put at the back of the global queue,
+/// so the tasks in the global queue and the other processors also get a chance to run
+/// before it is polled again.
+///
+/// [`yield_now`]: fn.yield_now.html
+#[inline(always)]
+pub async fn yield_to_global() {
+    machine::with_current_task(|task| task.set_yield_to_global());
+    lelet_utils::Yields(1).await;
+}
+
+/// Block current thread until the future is complete
+///
+/// Unlike [`lelet_utils::block_on`], it is safe to call this inside a task,
+/// the wait is a [`blocking`] section, so the processor is handed off to a new machine
+/// as soon as other tasks need it, without waiting for the blocking detection,
+/// and the current thread take it back after the future is complete.
+///
+/// [`lelet_utils::block_on`]: https://docs.rs/lelet-utils/*/lelet_utils/fn.block_on.html
+/// [`blocking`]: fn.blocking.html
+#[inline(always)]
+pub fn block_on<F: Future>(f: F) -> F::Output {
+    let mut f = f;
+
+    // the future is never moved
+    let mut f = unsafe { Pin::new_unchecked(&mut f) };
+
+    // dropped after the future is complete, on this thread
+    let mut guard = None;
+    lelet_utils::block_on(crate::chan::__poll_fn(|cx| match f.as_mut().poll(cx) {
+        Poll::Ready(val) => Poll::Ready(val),
+        Poll::Pending => {
+            // no need to enter blocking section if it is ready without blocking
+            guard.get_or_insert_with(BlockingGuard::enter);
+            Poll::Pending
+        }
+    }))
+}
+
+#[inline(always)]
+pub(crate) fn timer() -> &'static Driver {
+    system::get().timer()
 }
 
 /// Handle that you can `await` for
@@ -55,6 +304,63 @@ where
 /// [`spawn`]: fn.spawn.html
 pub struct JoinHandle<R>(async_task::JoinHandle<R, TaskTag>);
 
+impl<R> JoinHandle<R> {
+    /// Cancel the task
+    ///
+    /// The task will not be polled again, and the output is dropped if the task is already done.
+    ///
+    /// If the task is spawned via [`Context::spawn`], its context is also canceled.
+    ///
+    /// [`Context::spawn`]: context/struct.Context.html#method.spawn
+    #[inline(always)]
+    pub fn cancel(self) {
+        if let Some(ctx) = self.0.tag().context() {
+            ctx.cancel(ContextError::Canceled);
+        }
+        self.0.cancel();
+    }
+
+    /// Unique id of the task
+    #[inline(always)]
+    pub fn id(&self) -> usize {
+        self.0.tag().id()
+    }
+
+    /// Check if the task is done, without waiting for it
+    ///
+    /// [`try_join`] may still return `None` for a brief moment after this return true,
+    /// while the output is being stored.
+    ///
+    /// [`try_join`]: #method.try_join
+    #[inline(always)]
+    pub fn is_finished(&self) -> bool {
+        self.0.tag().is_finished()
+    }
+
+    /// Get the output if the task is done, without waiting for it
+    ///
+    /// # Panic
+    ///
+    /// When it is called again, or the handle is awaited, after the output is taken
+    #[inline(always)]
+    pub fn try_join(&mut self) -> Option<R> {
+        let waker = noop_waker();
+        match Pin::new(&mut self.0).poll(&mut Context::from_waker(&waker)) {
+            Poll::Pending => None,
+            Poll::Ready(Some(val)) => Some(val),
+            Poll::Ready(None) => panic!("the output is already taken"),
+        }
+    }
+
+    /// Statistics of the task, see [`TaskStats`]
+    ///
+    /// [`TaskStats`]: struct.TaskStats.html
+    #[inline(always)]
+    pub fn stats(&self) -> TaskStats {
+        self.0.tag().stats()
+    }
+}
+
 impl<R> Future for JoinHandle<R> {
     type Output = R;
 
@@ -63,7 +369,158 @@ impl<R> Future for JoinHandle<R> {
         match Pin::new(&mut self.0).poll(cx) {
             Poll::Pending => Poll::Pending,
             Poll::Ready(Some(val)) => Poll::Ready(val),
-            Poll::Ready(None) => unreachable!(), // we don't provide api to cancel the task
+            // cancel consume the handle, so it can only happen after try_join
+            Poll::Ready(None) => panic!("the output is already taken"),
         }
     }
 }
+
+impl<R> std::fmt::Debug for JoinHandle<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("id", &self.id())
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+#[inline(always)]
+fn noop_waker() -> Waker {
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(ptr::null(), &VTABLE),
+        |_| {},
+        |_| {},
+        |_| {},
+    );
+
+    unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn try_join() {
+        let (tx, rx) = crate::chan::bounded(1);
+
+        let mut handle = spawn(async move { rx.recv().await.unwrap() * 2 });
+        assert_ne!(handle.id(), spawn(async {}).id());
+
+        crate::block_until_idle();
+        assert!(!handle.is_finished());
+        assert_eq!(handle.try_join(), None);
+
+        block_on(tx.send(21)).unwrap();
+
+        crate::block_until_idle();
+        assert!(handle.is_finished());
+        assert_eq!(handle.try_join(), Some(42));
+
+        let stats = handle.stats();
+        assert_eq!(stats.polls, 2);
+        assert_eq!(stats.wakes, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "the output is already taken")]
+    fn try_join_twice() {
+        let mut handle = spawn(async {});
+        crate::block_until_idle();
+        assert_eq!(handle.try_join(), Some(()));
+        handle.try_join();
+    }
+
+    /// index of the processor that run the current task
+    fn current_processor() -> Option<usize> {
+        let mut index = None;
+        machine::with_current_task(|tag| {
+            index = Some(unsafe { &*tag.processor_hint() }.index());
+        });
+        index
+    }
+
+    #[test]
+    fn spawn_on_pinned() {
+        let num_cpus = system::get().processors().len();
+
+        let handles: Vec<_> = (0..num_cpus)
+            .map(|i| {
+                // keep the processor busy, so the others would steal if they could
+                spawn_on(i, async {
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                });
+
+                spawn_on(i, async move {
+                    for _ in 0..100 {
+                        assert_eq!(current_processor(), Some(i));
+                        yield_now().await;
+                    }
+                    current_processor()
+                })
+            })
+            .collect();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(block_on(handle), Some(i));
+        }
+    }
+
+    #[test]
+    fn spawn_near_hint() {
+        let num_cpus = system::get().processors().len();
+        let handles: Vec<_> = (0..num_cpus)
+            .map(|i| spawn_near(i, async move { i * 2 }))
+            .collect();
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(block_on(handle), i * 2);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "is out of bound")]
+    fn spawn_on_out_of_bound() {
+        spawn_on(system::get().processors().len(), async {});
+    }
+
+    #[test]
+    #[should_panic(expected = "is out of bound")]
+    fn spawn_near_out_of_bound() {
+        spawn_near(system::get().processors().len(), async {});
+    }
+
+    #[test]
+    fn block_on_inside_task() {
+        let handle = spawn(async {
+            // the spawned task need the processor that we are blocking
+            let val = block_on(async { spawn(async { 1 }).await + 1 });
+
+            // nested
+            let val = block_on(async {
+                let inner = spawn(async move { block_on(spawn(async move { val * 2 })) });
+                inner.await + 1
+            });
+
+            val
+        });
+
+        assert_eq!(block_on(handle), 5);
+    }
+
+    #[test]
+    fn block_on_keep_processor() {
+        // the processor is only handed off if other tasks need it,
+        // which can happen because of the other tests, so try a few times
+        let reclaimed = (0..10).any(|_| {
+            block_on(spawn(async {
+                block_on(yield_now());
+
+                let mut running = false;
+                machine::with_current_task(|_| running = true);
+                running
+            }))
+        });
+
+        assert!(reclaimed);
+    }
+}
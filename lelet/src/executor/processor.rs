@@ -1,7 +1,11 @@
 use std::ptr;
-use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicU8, Ordering};
 
-use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crossbeam_deque::{Injector, Steal};
+use crossbeam_utils::sync::{Parker, Unparker};
 use crossbeam_utils::Backoff;
 
 #[cfg(feature = "tracing")]
@@ -12,26 +16,70 @@ use log::trace;
 
 use lelet_utils::{SimpleLock, SimpleLockGuard};
 
+use super::histogram::Histogram;
 use super::machine::Machine;
-use super::system::System;
+use super::runq::{GlobalQueue, RunQueue};
+use super::system::{self, System};
+use super::task::{self, TaskTag};
 use super::Task;
 
+/// max number of tasks moved from global queue to local queue at once,
+/// half of the local queue, just like golang
+const GLOBAL_BATCH: usize = 128;
+
+/// states of [`Processor::handoff`]
+const IDLE: u8 = 0;
+/// the machine is in blocking section, but no one need the processor yet
+const BLOCKING: u8 = 1;
+/// new machine is spawned to take over the processor
+const HANDOFF: u8 = 2;
+
+/// the lock that must be held to push to or pop from the local queue,
+/// only held by the machine that currently run the processor
+type Owner<'a> = SimpleLockGuard<'a, ()>;
+
 /// Processor is the one who run the task
 pub struct Processor {
     #[cfg(feature = "tracing")]
     pub id: usize,
 
     system: Option<&'static System>,
+    index: usize,
     others: Vec<&'static Processor>,
 
-    last_seen: AtomicU64,
+    /// incremented when a task start and done running,
+    /// so it is odd when a task is running, used by sysmon for blocking detection
+    schedtick: AtomicU64,
+
+    /// poll duration, only recorded in adaptive blocking threshold mode
+    polls: Histogram,
 
     current_machine: AtomicPtr<Machine>,
     current_task: AtomicPtr<Task>,
 
-    global: Injector<Task>,
-    local: SimpleLock<Queue>,
-    stealers: [Stealer<Task>; 2],
+    /// one of `IDLE`, `BLOCKING` or `HANDOFF`,
+    /// the blocked machine can still take it back, see [`claim`] and [`reclaim`]
+    ///
+    /// [`claim`]: #method.claim
+    /// [`reclaim`]: #method.reclaim
+    handoff: AtomicU8,
+
+    global: GlobalQueue,
+
+    /// tasks that can only run on this processor, never stolen by others
+    pinned: Injector<Task>,
+
+    local: RunQueue,
+    owner: SimpleLock<()>,
+
+    /// for sleeping when there is no task
+    parker: Mutex<Parker>,
+    unparker: Unparker,
+
+    /// woken as spinning processor, see [`System::processors_wake_one`]
+    ///
+    /// [`System::processors_wake_one`]: ../system/struct.System.html#method.processors_wake_one
+    woken_spinning: AtomicBool,
 }
 
 impl Processor {
@@ -39,8 +87,8 @@ impl Processor {
         #[cfg(feature = "tracing")]
         static PROCESSOR_ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
-        let local = Queue::new();
-        let stealers = [local.worker.stealer(), local.slot.stealer()];
+        let parker = Parker::new();
+        let unparker = parker.unparker().clone();
 
         #[allow(clippy::let_and_return)]
         let processor = Processor {
@@ -48,16 +96,26 @@ impl Processor {
             id: PROCESSOR_ID_COUNTER.fetch_add(1, Ordering::Relaxed),
 
             system: None,
+            index: 0,
             others: vec![],
 
-            last_seen: AtomicU64::new(0),
+            schedtick: AtomicU64::new(0),
+            polls: Histogram::new(),
 
             current_machine: AtomicPtr::new(ptr::null_mut()),
             current_task: AtomicPtr::new(ptr::null_mut()),
 
-            global: Injector::new(),
-            local: SimpleLock::new(local),
-            stealers,
+            handoff: AtomicU8::new(IDLE),
+
+            global: GlobalQueue::new(),
+            pinned: Injector::new(),
+            local: RunQueue::new(),
+            owner: SimpleLock::new(()),
+
+            parker: Mutex::new(parker),
+            unparker,
+
+            woken_spinning: AtomicBool::new(false),
         };
 
         #[cfg(feature = "tracing")]
@@ -67,21 +125,53 @@ impl Processor {
     }
 
     #[inline(always)]
-    pub fn set_system(&mut self, system: &'static System, others: Vec<&'static Processor>) {
+    pub fn set_system(
+        &mut self,
+        system: &'static System,
+        index: usize,
+        others: Vec<&'static Processor>,
+    ) {
         let old = self.system.replace(system);
 
         // can only be set once
         assert!(old.is_none());
 
+        self.index = index;
         self.others = others;
     }
 
+    /// index in the system
+    #[inline(always)]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    #[inline(always)]
+    pub fn park(&self) {
+        self.parker.lock().unwrap().park();
+    }
+
+    #[inline(always)]
+    pub fn unpark(&self) {
+        self.unparker.unpark();
+    }
+
+    #[inline(always)]
+    pub fn set_woken_spinning(&self) {
+        self.woken_spinning.store(true, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub fn take_woken_spinning(&self) -> bool {
+        self.woken_spinning.swap(false, Ordering::Relaxed)
+    }
+
     #[inline(always)]
     pub fn run_on(&self, machine: &Machine) {
         macro_rules! check {
-            ($qlock:expr) => {
-                match $qlock {
-                    Some(qlock) => qlock,
+            ($owner:expr) => {
+                match $owner {
+                    Some(owner) => owner,
                     None => return,
                 }
             };
@@ -91,26 +181,47 @@ impl Processor {
         self.current_machine
             .store(machine as *const _ as *mut _, Ordering::Relaxed);
 
-        let mut qlock = check!(self.try_acquire_qlock(machine));
+        let mut owner = check!(self.try_acquire_owner(machine));
 
         // reset
         self.current_task.store(ptr::null_mut(), Ordering::Relaxed);
-        self.last_seen.store(u64::MAX, Ordering::Relaxed);
+        if self.schedtick.load(Ordering::Relaxed) % 2 == 1 {
+            // the old machine was running a task
+            self.schedtick.fetch_add(1, Ordering::Relaxed);
+        }
 
         #[cfg(feature = "tracing")]
         trace!("{:?} is now running on {:?} ", self, machine);
 
         let system = self.system.unwrap();
 
+        // counted as spinning processor until we find a task
+        let mut spinning = false;
+
         macro_rules! self_run_task {
             ($task:expr) => {
-                qlock = check!(self.run_task(machine, qlock, system.now(), $task));
+                if spinning {
+                    spinning = false;
+
+                    // the last spinning processor found task, more task may come,
+                    // wake another one to look for it
+                    if system.processors_stop_spinning() {
+                        system.processors_wake_one(ptr::null());
+                    }
+                }
+
+                owner = check!(self.run_task(machine, owner, $task));
             };
         }
 
         loop {
-            qlock.flush_slot();
-            if let Some(task) = self.pop_global(&qlock.worker) {
+            // give the tasks in the local queue a chance,
+            // tasks that keep spawning new task could occupy runnext forever
+            self.local.flush_runnext(&self.global);
+            if let Some(task) = self.pop_pinned() {
+                self_run_task!(task);
+            }
+            if let Some(task) = self.global.pop() {
                 self_run_task!(task);
             }
 
@@ -122,28 +233,42 @@ impl Processor {
                     };
                 }
 
-                if let Some(task) = qlock.pop() {
+                if let Some(task) = self.local.pop() {
                     run_task!(task);
                 }
 
                 // when local queue is empty:
 
-                // 1. get from global queue
-                if let Some(task) = self.pop_global(&qlock.worker) {
+                // 1. get from pinned queue
+                if let Some(task) = self.pop_pinned() {
+                    run_task!(task);
+                }
+
+                // 2. get from global queue
+                if let Some(task) = self.pop_global(&owner) {
                     run_task!(task);
                 }
 
-                // 2. steal from others
-                if let Some(task) = self.steal_others(&qlock.worker) {
+                // 3. steal from others
+                if let Some(task) = self.steal_others(&owner) {
                     run_task!(task);
                 }
 
-                // 3. no more task for now, just sleep
+                // 4. keep looking for a while, the task may come soon
+                if spinning || system.processors_start_spinning() {
+                    spinning = true;
+                    if let Some(task) = self.spin(&owner) {
+                        run_task!(task);
+                    }
+                    system.processors_stop_spinning();
+                }
+
+                // 5. no more task for now, just sleep
                 {
                     #[cfg(feature = "tracing")]
                     trace!("{:?} entering sleep", self);
 
-                    system.processors_wait_notif();
+                    spinning = system.processors_wait(self);
 
                     #[cfg(feature = "tracing")]
                     trace!("{:?} exiting sleep", self);
@@ -158,36 +283,60 @@ impl Processor {
     fn run_task<'a>(
         &'a self,
         machine: &Machine,
-        mut qlock: SimpleLockGuard<'a, Queue>,
-        now: u64,
+        mut owner: Owner<'a>,
         task: Task,
-    ) -> Option<SimpleLockGuard<'a, Queue>> {
+    ) -> Option<Owner<'a>> {
         #[cfg(feature = "tracing")]
         let task_info = format!("{:?}", task.tag());
 
         #[cfg(feature = "tracing")]
         trace!("{} is running on {:?} on {:?}", task_info, self, machine);
 
-        self.last_seen.store(now, Ordering::Relaxed);
+        let schedtick = self.schedtick.fetch_add(1, Ordering::Relaxed) + 1;
 
         self.current_task
             .store(task.tag() as *const _ as *mut _, Ordering::Relaxed);
 
-        qlock = match self.without_qlock(machine, qlock, || {
+        let start = if system::is_blocking_threshold_adaptive() {
+            Some(Instant::now())
+        } else {
+            None
+        };
+
+        owner = match self.without_owner(machine, owner, || {
             task.tag().set_processor_hint(self);
-            task.run();
+            task::run(task);
         }) {
-            Some(qlock) => qlock,
+            Some(owner) => {
+                // blocked polls are not counted in the poll duration,
+                // they would raise the adaptive blocking threshold
+                if let Some(start) = start {
+                    if !machine.is_blocking_at(schedtick) && !self.is_handed_off_from(machine) {
+                        self.polls.record(start.elapsed());
+                    }
+                }
+
+                // we are back before the new machine take over, cancel it
+                if self.end_handoff() {
+                    #[cfg(feature = "tracing")]
+                    trace!("{:?} is reclaimed by {:?}", self, machine);
+                }
+
+                owner
+            }
             None => {
                 #[cfg(feature = "tracing")]
                 trace!("{} is done running on {:?}", task_info, machine);
+
+                // the processor is stolen while we were blocked, we are leaving
+                system::remove_blocked_machine();
                 return None;
             }
         };
 
         self.current_task.store(ptr::null_mut(), Ordering::Relaxed);
 
-        self.last_seen.store(u64::MAX, Ordering::Relaxed);
+        self.schedtick.fetch_add(1, Ordering::Relaxed);
 
         #[cfg(feature = "tracing")]
         trace!(
@@ -197,12 +346,12 @@ impl Processor {
             machine
         );
 
-        Some(qlock)
+        Some(owner)
     }
 
     /// will fail if machine no longer hold the processor (stolen)
     #[inline(always)]
-    fn try_acquire_qlock(&self, machine: &Machine) -> Option<SimpleLockGuard<Queue>> {
+    fn try_acquire_owner(&self, machine: &Machine) -> Option<Owner<'_>> {
         let backoff = Backoff::new();
         loop {
             // fast check, without lock
@@ -210,14 +359,14 @@ impl Processor {
                 return None;
             }
 
-            if let Some(qlock) = self.local.try_lock() {
+            if let Some(owner) = self.owner.try_lock() {
                 // check again after locking
                 if !ptr::eq(self.current_machine.load(Ordering::Relaxed), machine) {
-                    drop(qlock);
+                    drop(owner);
                     return None;
                 }
 
-                return Some(qlock);
+                return Some(owner);
             }
 
             backoff.snooze();
@@ -225,80 +374,156 @@ impl Processor {
     }
 
     #[inline(always)]
-    fn without_qlock(
+    fn without_owner(
         &self,
         machine: &Machine,
-        qlock: SimpleLockGuard<Queue>,
+        owner: Owner,
         f: impl FnOnce(),
-    ) -> Option<SimpleLockGuard<Queue>> {
-        drop(qlock);
+    ) -> Option<Owner<'_>> {
+        drop(owner);
         f();
-        self.try_acquire_qlock(machine)
+        self.try_acquire_owner(machine)
     }
 
+    /// mark that the current machine is in blocking section,
+    /// the processor is only handed off when there is a task waiting for it,
+    /// return false if it is already marked
     #[inline(always)]
-    pub fn get_last_seen(&self) -> u64 {
-        self.last_seen.load(Ordering::Relaxed)
+    pub fn start_blocking(&self) -> bool {
+        self.handoff
+            .compare_exchange(IDLE, BLOCKING, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
     }
 
     #[inline(always)]
-    pub fn push_local(&self, machine: &Machine, task: Task) -> Result<(), Task> {
-        match self.try_acquire_qlock(machine) {
-            None => Err(task),
-            Some(qlock) => {
-                // if currently running task is rescheduled, it mean yielding
-                if ptr::eq(
-                    self.current_task.load(Ordering::Relaxed),
-                    task.tag() as *const _ as *mut _,
-                ) {
-                    #[cfg(feature = "tracing")]
-                    trace!(
-                        "{:?} is pushed to {:?}'s local queue (yielding)",
-                        task.tag(),
-                        self
-                    );
-
-                    qlock.push_into_worker(task);
-                } else {
-                    #[cfg(feature = "tracing")]
-                    trace!("{:?} is pushed to {:?}'s local queue", task.tag(), self);
+    pub fn is_blocking(&self) -> bool {
+        self.handoff.load(Ordering::Relaxed) == BLOCKING
+    }
 
-                    qlock.push_into_slot(task);
-                }
+    /// new machine is going to take over this processor, see [`start_handoff`]
+    ///
+    /// [`start_handoff`]: #method.start_handoff
+    #[inline(always)]
+    pub fn is_handed_off(&self) -> bool {
+        self.handoff.load(Ordering::Relaxed) == HANDOFF
+    }
 
-                Ok(())
+    /// mark that new machine is going to take over this processor,
+    /// return false if it is already marked
+    #[inline(always)]
+    pub fn start_handoff(&self) -> bool {
+        let mut state = self.handoff.load(Ordering::Relaxed);
+        while state != HANDOFF {
+            match self.handoff.compare_exchange_weak(
+                state,
+                HANDOFF,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(s) => state = s,
             }
         }
+        false
     }
 
+    /// return true if this processor is waiting for new machine,
+    /// and we take it, so the new machine must not run it
     #[inline(always)]
-    fn steal(&self, worker: &Worker<Task>) -> Steal<Task> {
-        match self.stealers[0].steal_batch_and_pop(worker) {
-            Steal::Success(task) => Steal::Success(task),
-            Steal::Empty => self.stealers[1].steal_batch_and_pop(worker),
-            Steal::Retry => Steal::Retry,
+    pub fn claim(&self) -> bool {
+        self.handoff.load(Ordering::Relaxed) == HANDOFF
+            && self
+                .handoff
+                .compare_exchange(HANDOFF, IDLE, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+    }
+
+    /// end blocking section or cancel the handoff,
+    /// return true if new machine was going to take over
+    ///
+    /// owner only
+    #[inline(always)]
+    fn end_handoff(&self) -> bool {
+        if self.handoff.load(Ordering::Relaxed) != IDLE
+            && self.handoff.swap(IDLE, Ordering::AcqRel) == HANDOFF
+        {
+            // we are not blocked anymore
+            system::remove_blocked_machine();
+            true
+        } else {
+            false
         }
     }
 
+    /// take back this processor if `machine` still own it
     #[inline(always)]
-    fn steal_others(&self, worker: &Worker<Task>) -> Option<Task> {
-        loop {
-            let mut retry = false;
+    pub fn reclaim(&self, machine: &Machine) -> bool {
+        // the owner lock prevent the new machine from running (and blocking) this processor,
+        // so the handoff we see is the one we started
+        match self.try_acquire_owner(machine) {
+            Some(_owner) => self.end_handoff(),
+            None => false,
+        }
+    }
 
-            for p in &self.others {
-                match p.steal(worker) {
-                    Steal::Success(task) => return Some(task),
-                    Steal::Empty => {}
-                    Steal::Retry => retry = true,
-                }
-            }
+    /// the task that currently running on `machine`, null if there is none
+    #[inline(always)]
+    pub fn current_task(&self, machine: &Machine) -> *const TaskTag {
+        if !ptr::eq(self.current_machine.load(Ordering::Relaxed), machine) {
+            return ptr::null();
+        }
+        self.current_task.load(Ordering::Relaxed) as *const TaskTag
+    }
 
-            if !retry {
-                return None;
+    #[inline(always)]
+    pub fn schedtick(&self) -> u64 {
+        self.schedtick.load(Ordering::Relaxed)
+    }
+
+    /// poll duration percentile, see [`Histogram::percentile`]
+    ///
+    /// [`Histogram::percentile`]: ../histogram/struct.Histogram.html#method.percentile
+    #[inline(always)]
+    pub fn poll_percentile(&self, percent: u32) -> Option<Duration> {
+        self.polls.percentile(percent)
+    }
+
+    /// new machine is spawned to take over this processor, or already took it over from `machine`
+    #[inline(always)]
+    pub fn is_handed_off_from(&self, machine: &Machine) -> bool {
+        !ptr::eq(self.current_machine.load(Ordering::Relaxed), machine) || self.is_handed_off()
+    }
+
+    /// if `next` is true, the task will run next, see [`RunQueue::push`]
+    ///
+    /// [`RunQueue::push`]: ../runq/struct.RunQueue.html#method.push
+    #[inline(always)]
+    pub fn push_local(&self, machine: &Machine, task: Task, next: bool) -> Result<(), Task> {
+        match self.try_acquire_owner(machine) {
+            None => Err(task),
+            Some(_owner) => {
+                #[cfg(feature = "tracing")]
+                trace!(
+                    "{:?} is pushed to {:?}'s local queue{}",
+                    task.tag(),
+                    self,
+                    if next { " (next)" } else { "" }
+                );
+
+                self.local.push(task, next, &self.global);
+
+                Ok(())
             }
         }
     }
 
+    #[inline(always)]
+    fn steal_others(&self, _owner: &Owner) -> Option<Task> {
+        self.others
+            .iter()
+            .find_map(|p| self.local.steal_from(&p.local))
+    }
+
     #[inline(always)]
     pub fn push_global(&self, task: Task) {
         #[cfg(feature = "tracing")]
@@ -308,35 +533,68 @@ impl Processor {
     }
 
     #[inline(always)]
-    fn pop_global(&self, worker: &Worker<Task>) -> Option<Task> {
-        loop {
-            let mut retry = false;
+    pub fn push_pinned(&self, task: Task) {
+        #[cfg(feature = "tracing")]
+        trace!("{:?} is pushed to {:?}'s pinned queue", task.tag(), self);
 
-            // check dedicated global queue first
-            match self.global.steal_batch_and_pop(worker) {
-                Steal::Success(task) => return Some(task),
-                Steal::Empty => {}
-                Steal::Retry => retry = true,
-            }
+        self.pinned.push(task);
+    }
 
-            // then steal from others global queue
-            for p in &self.others {
-                match p.global.steal_batch_and_pop(worker) {
-                    Steal::Success(task) => return Some(task),
-                    Steal::Empty => {}
-                    Steal::Retry => retry = true,
-                }
-            }
+    #[inline(always)]
+    pub fn has_pinned(&self) -> bool {
+        !self.pinned.is_empty()
+    }
+
+    #[inline(always)]
+    fn pop_pinned(&self) -> Option<Task> {
+        pop_injector(&self.pinned)
+    }
 
-            if !retry {
+    #[inline(always)]
+    fn pop_global(&self, _owner: &Owner) -> Option<Task> {
+        // check dedicated global queue first,
+        // then steal from others global queue
+        std::iter::once(self)
+            .chain(self.others.iter().copied())
+            // take some more, so we don't need to touch global queue too often,
+            // the local queue is empty, so there is room for them
+            .find_map(|p| p.global.pop_into(&self.local, GLOBAL_BATCH))
+    }
+
+    /// look for task until spin duration is elapsed
+    #[inline(always)]
+    fn spin(&self, owner: &Owner) -> Option<Task> {
+        #[cfg(feature = "tracing")]
+        trace!("{:?} is spinning", self);
+
+        let deadline = Instant::now() + system::spin_duration();
+        let backoff = Backoff::new();
+        loop {
+            if let Some(task) = self.pop_pinned() {
+                return Some(task);
+            }
+            if let Some(task) = self.pop_global(owner) {
+                return Some(task);
+            }
+            if let Some(task) = self.steal_others(owner) {
+                return Some(task);
+            }
+            if Instant::now() >= deadline {
                 return None;
             }
+            backoff.snooze();
         }
     }
 
+    /// is there any task that others can take
+    #[inline(always)]
+    pub fn has_stealable(&self) -> bool {
+        !self.global.is_empty() || !self.local.is_empty()
+    }
+
     #[inline(always)]
     pub fn is_empty(&self) -> bool {
-        self.global.is_empty() && self.stealers[0].is_empty() && self.stealers[1].is_empty()
+        self.global.is_empty() && self.pinned.is_empty() && self.local.is_empty()
     }
 }
 
@@ -347,41 +605,87 @@ impl std::fmt::Debug for Processor {
     }
 }
 
-struct Queue {
-    slot: Worker<Task>,
-    worker: Worker<Task>,
-}
-
-impl Queue {
-    fn new() -> Queue {
-        Queue {
-            slot: Worker::new_lifo(),
-            worker: Worker::new_fifo(),
+#[inline(always)]
+fn pop_injector(injector: &Injector<Task>) -> Option<Task> {
+    loop {
+        match injector.steal() {
+            Steal::Success(task) => return Some(task),
+            Steal::Empty => return None,
+            Steal::Retry => {}
         }
     }
+}
 
-    #[inline(always)]
-    fn flush_slot(&self) {
-        let slot_stealer = self.slot.stealer();
-        loop {
-            if let Steal::Empty = slot_stealer.steal_batch(&self.worker) {
-                break;
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handoff_states() {
+        let p = Processor::new();
+        assert!(!p.is_blocking());
+        assert!(!p.is_handed_off());
+
+        // nothing to claim
+        assert!(!p.claim());
+
+        assert!(p.start_blocking());
+        assert!(!p.start_blocking());
+        assert!(p.is_blocking());
+
+        // from blocking section
+        assert!(p.start_handoff());
+        assert!(!p.start_handoff());
+        assert!(p.is_handed_off());
+        assert!(!p.is_blocking());
+
+        // the new machine take it
+        assert!(p.claim());
+        assert!(!p.claim());
+        assert!(!p.is_handed_off());
+
+        // from running, by sysmon
+        assert!(p.start_handoff());
+        assert!(!p.start_blocking());
+        assert!(p.claim());
+        assert!(p.start_blocking());
     }
 
-    #[inline(always)]
-    fn pop(&self) -> Option<Task> {
-        self.slot.pop().or_else(|| self.worker.pop())
-    }
+    #[test]
+    fn reclaim() {
+        crate::fresh_runtime::__run_in_fresh_runtime(module_path!(), "reclaim", || {
+            system::set_num_cpus(1).unwrap();
+            let p = system::get().processor(0);
+
+            for i in 0..100 {
+                crate::block_on(crate::spawn(async move {
+                    let guard = crate::BlockingGuard::enter();
+                    assert!(p.is_blocking());
+
+                    // handed off only when there is other task waiting
+                    let handle = if i % 2 == 0 {
+                        Some(crate::spawn(async {}))
+                    } else {
+                        assert!(!p.is_handed_off());
+                        None
+                    };
 
-    #[inline(always)]
-    fn push_into_slot(&self, task: Task) {
-        self.slot.push(task);
-    }
+                    // either we take it back, or the new machine already took it over
+                    drop(guard);
+                    assert!(!p.is_blocking());
+                    assert!(!p.is_handed_off());
 
-    #[inline(always)]
-    fn push_into_worker(&self, task: Task) {
-        self.worker.push(task);
+                    if let Some(handle) = handle {
+                        handle.await;
+                    }
+                }));
+
+                crate::block_until_idle();
+                assert_eq!(crate::metrics().blocked_machines, 0);
+            }
+
+            // not an incident
+            assert_eq!(crate::metrics().blocking_incidents, 0);
+        });
     }
 }
@@ -0,0 +1,211 @@
+use std::future::Future;
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::context::Context;
+
+use super::blocking::blocking;
+
+/// What to do with the tasks that are still running when the main future is done,
+/// see [`run_with`]
+///
+/// [`run_with`]: fn.run_with.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitPolicy {
+    /// Return right away, just like golang, the tasks are left behind,
+    /// and they are gone when the program exit
+    Exit,
+
+    /// Wait until all the tasks spawned by the main future are done
+    Wait,
+
+    /// Cancel the context of the main future, then wait like [`Wait`]
+    ///
+    /// Cancellation is cooperative, see [`Context`].
+    ///
+    /// [`Wait`]: #variant.Wait
+    /// [`Context`]: context/struct.Context.html
+    Cancel,
+}
+
+/// Run the main future in the executor, and return its output
+///
+/// Just like `main` in golang, the tasks that are still running are left behind,
+/// same as [`run_with`] with [`ExitPolicy::Exit`].
+///
+/// [`run_with`]: fn.run_with.html
+/// [`ExitPolicy::Exit`]: enum.ExitPolicy.html#variant.Exit
+#[inline(always)]
+pub fn run<F, R>(main: F) -> R
+where
+    F: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    run_with(ExitPolicy::Exit, main)
+}
+
+/// Run the main future in the executor, and return its output
+/// after the outstanding tasks are handled according to `policy`
+///
+/// The main future run with its own context, derived from [`Context::background`],
+/// the tasks spawned by it (directly or indirectly) inherit that context,
+/// and every future attached to it (see [`Context::attach`]) is counted
+/// until it is done or dropped (e.g. canceled via [`JoinHandle::cancel`]).
+///
+/// [`Context::background`]: context/struct.Context.html#method.background
+/// [`Context::attach`]: context/struct.Context.html#method.attach
+/// [`JoinHandle::cancel`]: struct.JoinHandle.html#method.cancel
+#[inline(always)]
+pub fn run_with<F, R>(policy: ExitPolicy, main: F) -> R
+where
+    F: Future<Output = R> + Send + 'static,
+    R: Send + 'static,
+{
+    let live = Arc::new(Live {
+        count: Mutex::new(0),
+        done: Condvar::new(),
+    });
+
+    let (ctx, cancel) = Context::background().with_cancel();
+    let ctx = ctx.with_value(Outstanding(live.clone()));
+
+    let output = super::block_on(super::spawn_with_context(ctx, main));
+
+    match policy {
+        ExitPolicy::Exit => return output,
+        ExitPolicy::Wait => {}
+        ExitPolicy::Cancel => cancel.cancel(),
+    }
+
+    blocking(|| {
+        let mut count = live.count.lock().unwrap();
+        while *count > 0 {
+            count = live.done.wait(count).unwrap();
+        }
+    });
+
+    output
+}
+
+/// the value of the context of the main future, see [`track`]
+///
+/// [`track`]: fn.track.html
+struct Outstanding(Arc<Live>);
+
+/// number of futures attached to the context of the main future
+struct Live {
+    count: Mutex<usize>,
+    done: Condvar,
+}
+
+/// count the future that is going to be attached to `ctx` as live until
+/// the returned guard is dropped, `None` if `ctx` is not from [`run_with`]
+///
+/// [`run_with`]: fn.run_with.html
+#[inline(always)]
+pub(crate) fn track(ctx: &Context) -> Option<Tracked> {
+    let live = &ctx.value::<Outstanding>()?.0;
+    *live.count.lock().unwrap() += 1;
+    Some(Tracked(live.clone()))
+}
+
+/// returned by [`track`]
+///
+/// [`track`]: fn.track.html
+pub(crate) struct Tracked(Arc<Live>);
+
+impl Drop for Tracked {
+    #[inline(always)]
+    fn drop(&mut self) {
+        let mut count = self.0.count.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.0.done.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn exit() {
+        let done = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = crate::chan::bounded(1);
+
+        let output = run_with(ExitPolicy::Exit, {
+            let done = done.clone();
+            async move {
+                crate::spawn(async move {
+                    rx.recv().await.unwrap();
+                    done.store(true, Ordering::SeqCst);
+                });
+                5
+            }
+        });
+        assert_eq!(output, 5);
+
+        // left behind, but still running
+        assert!(!done.load(Ordering::SeqCst));
+        crate::block_on(tx.send(())).unwrap();
+        while !done.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn wait() {
+        let done = Arc::new(AtomicBool::new(false));
+        let leaked = Arc::new(Mutex::new(None));
+
+        let output = run_with(ExitPolicy::Wait, {
+            let done = done.clone();
+            let leaked = leaked.clone();
+            async move {
+                // the context outlive the tasks, it doesn't matter
+                leaked.lock().unwrap().replace(Context::current());
+
+                // canceled task is not waited
+                crate::spawn(pending::<()>()).cancel();
+
+                // nor the one that is done
+                crate::spawn(async {}).await;
+
+                crate::spawn(async move {
+                    crate::spawn(async move {
+                        crate::time::sleep(Duration::from_millis(50)).await;
+                        done.store(true, Ordering::SeqCst);
+                    });
+                });
+                5
+            }
+        });
+        assert_eq!(output, 5);
+
+        // including the one spawned indirectly
+        assert!(done.load(Ordering::SeqCst));
+        assert!(leaked.lock().unwrap().is_some());
+    }
+
+    #[test]
+    fn cancel() {
+        let done = Arc::new(AtomicBool::new(false));
+
+        let output = run_with(ExitPolicy::Cancel, {
+            let done = done.clone();
+            async move {
+                crate::spawn(async move {
+                    Context::current().done().await;
+                    done.store(true, Ordering::SeqCst);
+                });
+                5
+            }
+        });
+        assert_eq!(output, 5);
+        assert!(done.load(Ordering::SeqCst));
+    }
+}
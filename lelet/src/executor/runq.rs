@@ -0,0 +1,467 @@
+use std::collections::VecDeque;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crossbeam_utils::CachePadded;
+
+use super::task::TaskTag;
+use super::Task;
+
+/// capacity of the local run queue
+const SIZE: u32 = 256;
+
+/// Local run queue of a processor, just like `runq` in golang
+///
+/// Fixed-size ring buffer with an extra `runnext` slot,
+/// tasks are stored as raw pointer (see [`Task::into_raw`]).
+///
+/// Only the machine that currently own the processor can push and pop from it,
+/// others can only steal from it.
+///
+/// [`Task::into_raw`]: https://docs.rs/async-task/3.0.0/async_task/struct.Task.html#method.into_raw
+pub struct RunQueue {
+    /// next slot to consume, modified by owner and stealers
+    head: CachePadded<AtomicU32>,
+
+    /// next slot to produce, only modified by owner
+    tail: CachePadded<AtomicU32>,
+
+    buffer: Box<[AtomicPtr<TaskTag>]>,
+
+    /// task that will run next, before the tasks in the buffer
+    runnext: CachePadded<AtomicPtr<TaskTag>>,
+}
+
+impl RunQueue {
+    #[inline(always)]
+    pub fn new() -> RunQueue {
+        RunQueue {
+            head: CachePadded::new(AtomicU32::new(0)),
+            tail: CachePadded::new(AtomicU32::new(0)),
+            buffer: (0..SIZE).map(|_| AtomicPtr::new(ptr::null_mut())).collect(),
+            runnext: CachePadded::new(AtomicPtr::new(ptr::null_mut())),
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        let h = self.head.load(Ordering::Acquire);
+        let t = self.tail.load(Ordering::Acquire);
+        h == t && self.runnext.load(Ordering::Acquire).is_null()
+    }
+
+    /// push the task, if `next` is true, it will be put in `runnext` slot,
+    /// kicking out the old one to the back of the queue
+    ///
+    /// when the queue is full, half of it is moved to `overflow`
+    ///
+    /// owner only
+    #[inline(always)]
+    pub fn push(&self, task: Task, next: bool, overflow: &GlobalQueue) {
+        let mut task = into_raw(task);
+
+        if next {
+            task = self.runnext.swap(task, Ordering::AcqRel);
+            if task.is_null() {
+                return;
+            }
+        }
+
+        loop {
+            let h = self.head.load(Ordering::Acquire);
+            let t = self.tail.load(Ordering::Relaxed);
+
+            if t.wrapping_sub(h) < SIZE {
+                self.slot(t).store(task, Ordering::Relaxed);
+                self.tail.store(t.wrapping_add(1), Ordering::Release);
+                return;
+            }
+
+            if self.push_slow(task, h, t, overflow) {
+                return;
+            }
+
+            // the queue is not full anymore (stolen), try again
+        }
+    }
+
+    /// move `task` and half of the queue to `overflow`
+    #[inline(always)]
+    fn push_slow(&self, task: *mut TaskTag, h: u32, t: u32, overflow: &GlobalQueue) -> bool {
+        let n = t.wrapping_sub(h) / 2;
+        debug_assert_eq!(n, SIZE / 2);
+
+        if self
+            .head
+            .compare_exchange(h, h.wrapping_add(n), Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+
+        // the slots are ours now, only the owner write to the slots
+        let batch = (0..n)
+            .map(|i| self.slot(h.wrapping_add(i)).load(Ordering::Relaxed))
+            .chain(std::iter::once(task))
+            .map(|task| unsafe { from_raw(task) });
+        overflow.push_batch(batch);
+
+        true
+    }
+
+    /// put all the tasks in the back of the queue,
+    /// the queue must have room for all of them
+    ///
+    /// owner only
+    #[inline(always)]
+    fn push_batch(&self, tasks: impl ExactSizeIterator<Item = Task>) {
+        let h = self.head.load(Ordering::Acquire);
+        let t = self.tail.load(Ordering::Relaxed);
+        assert!(tasks.len() as u32 <= SIZE - t.wrapping_sub(h));
+
+        let mut n = 0;
+        for task in tasks {
+            self.slot(t.wrapping_add(n))
+                .store(into_raw(task), Ordering::Relaxed);
+            n += 1;
+        }
+        self.tail.store(t.wrapping_add(n), Ordering::Release);
+    }
+
+    /// move the task in `runnext` slot to the back of the queue
+    ///
+    /// owner only
+    #[inline(always)]
+    pub fn flush_runnext(&self, overflow: &GlobalQueue) {
+        let task = self.runnext.swap(ptr::null_mut(), Ordering::AcqRel);
+        if !task.is_null() {
+            self.push(unsafe { from_raw(task) }, false, overflow);
+        }
+    }
+
+    /// owner only
+    #[inline(always)]
+    pub fn pop(&self) -> Option<Task> {
+        // avoid the swap when there is nothing in runnext
+        if !self.runnext.load(Ordering::Relaxed).is_null() {
+            let next = self.runnext.swap(ptr::null_mut(), Ordering::AcqRel);
+            if !next.is_null() {
+                return Some(unsafe { from_raw(next) });
+            }
+        }
+
+        loop {
+            let h = self.head.load(Ordering::Acquire);
+            let t = self.tail.load(Ordering::Relaxed);
+
+            if h == t {
+                return None;
+            }
+
+            let task = self.slot(h).load(Ordering::Relaxed);
+            if self
+                .head
+                .compare_exchange(h, h.wrapping_add(1), Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Some(unsafe { from_raw(task) });
+            }
+        }
+    }
+
+    /// steal half of the tasks from `victim`, put them in this queue, and return one of them
+    ///
+    /// owner only (of this queue), this queue must be empty
+    #[inline(always)]
+    pub fn steal_from(&self, victim: &RunQueue) -> Option<Task> {
+        let t = self.tail.load(Ordering::Relaxed);
+
+        let n = victim.grab(self, t);
+        if n == 0 {
+            return None;
+        }
+
+        // return the last one, and publish the rest
+        let n = n - 1;
+        let task = self.slot(t.wrapping_add(n)).load(Ordering::Relaxed);
+        if n > 0 {
+            self.tail.store(t.wrapping_add(n), Ordering::Release);
+        }
+
+        Some(unsafe { from_raw(task) })
+    }
+
+    /// copy half of the tasks into `dst` buffer starting from `dst_tail`,
+    /// return the number of task copied
+    #[inline(always)]
+    fn grab(&self, dst: &RunQueue, dst_tail: u32) -> u32 {
+        loop {
+            let h = self.head.load(Ordering::Acquire);
+            let t = self.tail.load(Ordering::Acquire);
+
+            let n = t.wrapping_sub(h);
+            let n = n - n / 2;
+
+            if n == 0 {
+                // last resort, steal the runnext
+                let next = self.runnext.load(Ordering::Acquire);
+                if next.is_null() {
+                    return 0;
+                }
+                if self
+                    .runnext
+                    .compare_exchange(next, ptr::null_mut(), Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    dst.slot(dst_tail).store(next, Ordering::Relaxed);
+                    return 1;
+                }
+                continue;
+            }
+
+            // inconsistent h and t, read again
+            if n > SIZE / 2 {
+                continue;
+            }
+
+            for i in 0..n {
+                let task = self.slot(h.wrapping_add(i)).load(Ordering::Relaxed);
+                dst.slot(dst_tail.wrapping_add(i))
+                    .store(task, Ordering::Relaxed);
+            }
+
+            if self
+                .head
+                .compare_exchange(h, h.wrapping_add(n), Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return n;
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn slot(&self, i: u32) -> &AtomicPtr<TaskTag> {
+        unsafe { self.buffer.get_unchecked((i % SIZE) as usize) }
+    }
+}
+
+/// Global run queue of a processor, just like `globrunq` in golang
+///
+/// Protected by a lock, but the tasks are moved from and to [`RunQueue`] in batch,
+/// so the lock is taken once for many tasks.
+///
+/// [`RunQueue`]: struct.RunQueue.html
+pub struct GlobalQueue {
+    /// so checking for emptiness doesn't need the lock
+    len: AtomicUsize,
+    queue: Mutex<VecDeque<Task>>,
+}
+
+impl GlobalQueue {
+    #[inline(always)]
+    pub fn new() -> GlobalQueue {
+        GlobalQueue {
+            len: AtomicUsize::new(0),
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len.load(Ordering::Acquire) == 0
+    }
+
+    #[inline(always)]
+    pub fn push(&self, task: Task) {
+        self.push_batch(std::iter::once(task));
+    }
+
+    #[inline(always)]
+    pub fn push_batch(&self, tasks: impl IntoIterator<Item = Task>) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(tasks);
+        self.len.store(queue.len(), Ordering::Release);
+    }
+
+    #[inline(always)]
+    pub fn pop(&self) -> Option<Task> {
+        // avoid the lock when there is nothing to pop
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut queue = self.queue.lock().unwrap();
+        let task = queue.pop_front();
+        self.len.store(queue.len(), Ordering::Release);
+
+        task
+    }
+
+    /// pop one task, and move up to `max` more tasks to `local`,
+    /// `local` must have room for them
+    ///
+    /// owner only (of `local`)
+    #[inline(always)]
+    pub fn pop_into(&self, local: &RunQueue, max: usize) -> Option<Task> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut queue = self.queue.lock().unwrap();
+        let task = queue.pop_front();
+        let n = std::cmp::min(queue.len(), max);
+        local.push_batch(queue.drain(..n));
+        self.len.store(queue.len(), Ordering::Release);
+
+        task
+    }
+}
+
+#[inline(always)]
+fn into_raw(task: Task) -> *mut TaskTag {
+    task.into_raw() as *mut TaskTag
+}
+
+#[inline(always)]
+unsafe fn from_raw(task: *mut TaskTag) -> Task {
+    Task::from_raw(task)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task() -> Task {
+        let (task, _) = async_task::spawn(async {}, |_| {}, TaskTag::new(None));
+        task
+    }
+
+    fn id(task: Option<Task>) -> Option<usize> {
+        task.map(|task| task.tag().id())
+    }
+
+    #[test]
+    fn push_pop() {
+        let global = GlobalQueue::new();
+        let q = RunQueue::new();
+        assert!(q.is_empty());
+
+        let tasks: Vec<_> = (0..10).map(|_| task()).collect();
+        let ids: Vec<_> = tasks.iter().map(|task| task.tag().id()).collect();
+        for task in tasks {
+            q.push(task, false, &global);
+        }
+
+        for &i in &ids {
+            assert_eq!(id(q.pop()), Some(i));
+        }
+        assert!(q.pop().is_none());
+        assert!(q.is_empty());
+        assert!(global.is_empty());
+    }
+
+    #[test]
+    fn runnext() {
+        let global = GlobalQueue::new();
+        let q = RunQueue::new();
+
+        let (a, b, c) = (task(), task(), task());
+        let (ia, ib, ic) = (a.tag().id(), b.tag().id(), c.tag().id());
+
+        q.push(a, false, &global);
+        q.push(b, true, &global);
+        assert!(!q.is_empty());
+
+        // kick b to the back of the queue
+        q.push(c, true, &global);
+
+        assert_eq!(id(q.pop()), Some(ic));
+        assert_eq!(id(q.pop()), Some(ia));
+        assert_eq!(id(q.pop()), Some(ib));
+        assert!(q.pop().is_none());
+
+        let d = task();
+        let id_d = d.tag().id();
+        q.push(d, true, &global);
+        q.flush_runnext(&global);
+        assert_eq!(id(q.pop()), Some(id_d));
+    }
+
+    #[test]
+    fn overflow() {
+        let global = GlobalQueue::new();
+        let q = RunQueue::new();
+
+        let tasks: Vec<_> = (0..=SIZE).map(|_| task()).collect();
+        let ids: Vec<_> = tasks.iter().map(|task| task.tag().id()).collect();
+        for task in tasks {
+            q.push(task, false, &global);
+        }
+
+        // the older half and the new task are moved to the global queue
+        let half = (SIZE / 2) as usize;
+        for &i in &ids[..half] {
+            assert_eq!(id(global.pop()), Some(i));
+        }
+        assert_eq!(id(global.pop()), Some(ids[SIZE as usize]));
+        assert!(global.is_empty());
+
+        for &i in &ids[half..SIZE as usize] {
+            assert_eq!(id(q.pop()), Some(i));
+        }
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn steal() {
+        let global = GlobalQueue::new();
+        let (victim, thief) = (RunQueue::new(), RunQueue::new());
+        assert!(thief.steal_from(&victim).is_none());
+
+        let tasks: Vec<_> = (0..10).map(|_| task()).collect();
+        let ids: Vec<_> = tasks.iter().map(|task| task.tag().id()).collect();
+        for task in tasks {
+            victim.push(task, false, &global);
+        }
+
+        // take the older half, the last one is returned
+        assert_eq!(id(thief.steal_from(&victim)), Some(ids[4]));
+        for &i in &ids[..4] {
+            assert_eq!(id(thief.pop()), Some(i));
+        }
+        assert!(thief.is_empty());
+
+        for &i in &ids[5..] {
+            assert_eq!(id(victim.pop()), Some(i));
+        }
+
+        // only runnext is left
+        let a = task();
+        let ia = a.tag().id();
+        victim.push(a, true, &global);
+        assert_eq!(id(thief.steal_from(&victim)), Some(ia));
+        assert!(victim.is_empty());
+    }
+
+    #[test]
+    fn global_pop_into() {
+        let global = GlobalQueue::new();
+        let q = RunQueue::new();
+        assert!(global.pop_into(&q, 2).is_none());
+
+        let tasks: Vec<_> = (0..5).map(|_| task()).collect();
+        let ids: Vec<_> = tasks.iter().map(|task| task.tag().id()).collect();
+        global.push_batch(tasks);
+
+        assert_eq!(id(global.pop_into(&q, 2)), Some(ids[0]));
+        assert_eq!(id(q.pop()), Some(ids[1]));
+        assert_eq!(id(q.pop()), Some(ids[2]));
+        assert!(q.is_empty());
+
+        assert_eq!(id(global.pop()), Some(ids[3]));
+        assert_eq!(id(global.pop()), Some(ids[4]));
+        assert!(global.is_empty());
+    }
+}
@@ -0,0 +1,234 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll};
+
+use super::blocking::blocking;
+use super::JoinHandle;
+
+/// Scope for spawning task that can borrow non-`'static` data
+///
+/// this struct is created by [`scope`]
+///
+/// [`scope`]: fn.scope.html
+pub struct Scope<'env> {
+    pending: Arc<Pending>,
+
+    // invariant over 'env
+    _marker: PhantomData<&'env mut &'env ()>,
+}
+
+#[derive(Default)]
+struct Pending {
+    count: Mutex<usize>,
+    done: Condvar,
+}
+
+impl Pending {
+    #[inline(always)]
+    fn add(&self) {
+        *self.count.lock().unwrap() += 1;
+    }
+
+    #[inline(always)]
+    fn sub(&self) {
+        let mut count = self.count.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    #[inline(always)]
+    fn wait(&self) {
+        let mut count = self.count.lock().unwrap();
+        while *count != 0 {
+            count = self.done.wait(count).unwrap();
+        }
+    }
+}
+
+/// decrement the pending counter when the task is done or dropped
+struct PendingGuard(Arc<Pending>);
+
+impl Drop for PendingGuard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.0.sub();
+    }
+}
+
+/// the future spawned by [`Scope::spawn`]
+///
+/// fields are dropped in declaration order, so `future` (and everything it borrow)
+/// is always dropped before `_guard` tell the scope that the task is done,
+/// even when the task is cancelled before it is polled
+///
+/// [`Scope::spawn`]: struct.Scope.html#method.spawn
+struct ScopedTask<T> {
+    future: Pin<Box<T>>,
+    _guard: PendingGuard,
+}
+
+impl<T: Future> Future for ScopedTask<T> {
+    type Output = T::Output;
+
+    #[inline(always)]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T::Output> {
+        self.future.as_mut().poll(cx)
+    }
+}
+
+impl<'env> Scope<'env> {
+    /// Run the task in the background, the task can borrow data that outlive the scope.
+    ///
+    /// The task is guaranteed to be done before [`scope`] return.
+    ///
+    /// [`scope`]: fn.scope.html
+    #[inline(always)]
+    pub fn spawn<T, R>(&self, task: T) -> JoinHandle<R>
+    where
+        T: Future<Output = R> + Send + 'env,
+        R: Send + 'static,
+    {
+        self.pending.add();
+        let task: Pin<Box<dyn Future<Output = R> + Send + 'env>> = Box::pin(ScopedTask {
+            future: Box::pin(task),
+            _guard: PendingGuard(self.pending.clone()),
+        });
+
+        // this is safe because we wait for all the spawned task to be done (or dropped)
+        // before the scope return, so everything borrowed by the task outlive the task itself
+        let task: Pin<Box<dyn Future<Output = R> + Send + 'static>> =
+            unsafe { mem::transmute(task) };
+
+        super::spawn(task)
+    }
+}
+
+impl Drop for Scope<'_> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        // don't hold the processor while waiting, the pending task may need it
+        blocking(|| self.pending.wait());
+    }
+}
+
+/// Create a scope for spawning task that can borrow non-`'static` data
+///
+/// All the task spawned via [`Scope::spawn`] are guaranteed to be done before this function
+/// return, even when `f` panic.
+///
+/// This function will block current thread until all the task are done,
+/// inside a task, the wait is a [`blocking`] section, so the processor is handed off
+/// to run the other task in the meantime.
+///
+/// There is no async variant of this function, because a future can be leaked
+/// (via [`mem::forget`]) and then the borrowed data will outlive the scope.
+///
+/// The output of the task must still be `'static`, write the result into the borrowed data
+/// if you need to return non-`'static` value.
+///
+/// [`Scope::spawn`]: struct.Scope.html#method.spawn
+/// [`blocking`]: fn.blocking.html
+/// [`mem::forget`]: https://doc.rust-lang.org/std/mem/fn.forget.html
+#[inline(always)]
+pub fn scope<'env, F, R>(f: F) -> R
+where
+    F: FnOnce(&Scope<'env>) -> R,
+{
+    let scope = Scope {
+        pending: Arc::new(Pending::default()),
+        _marker: PhantomData,
+    };
+
+    // scope will wait all the pending task when dropped
+    f(&scope)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn borrow() {
+        let count = AtomicUsize::new(0);
+        let mut data = vec![1, 2, 3];
+
+        scope(|s| {
+            for _ in 0..10 {
+                s.spawn(async {
+                    count.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            s.spawn(async {
+                data.push(4);
+            });
+        });
+
+        assert_eq!(count.load(Ordering::SeqCst), 10);
+        assert_eq!(data, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn nested() {
+        let count = AtomicUsize::new(0);
+
+        scope(|s| {
+            s.spawn(async {
+                scope(|s| {
+                    for _ in 0..10 {
+                        s.spawn(async {
+                            count.fetch_add(1, Ordering::SeqCst);
+                        });
+                    }
+                });
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        });
+
+        assert_eq!(count.load(Ordering::SeqCst), 11);
+    }
+
+    #[test]
+    fn cancel() {
+        struct SetOnDrop<'a>(&'a AtomicBool);
+
+        impl Drop for SetOnDrop<'_> {
+            fn drop(&mut self) {
+                // make it obvious if the scope doesn't wait for us
+                thread::sleep(Duration::from_millis(100));
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = AtomicBool::new(false);
+        let polled = AtomicBool::new(false);
+
+        // inside a task, so the scoped task is not polled before it is canceled
+        let done = scope(|outer| {
+            crate::block_on(outer.spawn(async {
+                scope(|s| {
+                    let guard = SetOnDrop(&dropped);
+                    s.spawn(async {
+                        let _guard = guard;
+                        polled.store(true, Ordering::SeqCst);
+                        pending::<()>().await
+                    })
+                    .cancel();
+                });
+                dropped.load(Ordering::SeqCst)
+            }))
+        });
+
+        assert!(done);
+        assert!(!polled.load(Ordering::SeqCst));
+    }
+}
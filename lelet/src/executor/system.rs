@@ -1,8 +1,9 @@
 use std::ptr;
-use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
-use std::sync::{Mutex, MutexGuard, Once};
+use std::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+use std::task::Waker;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossbeam_utils::sync::{Parker, Unparker};
 use crossbeam_utils::CachePadded;
@@ -12,26 +13,46 @@ use log::trace;
 
 use lelet_utils::{abort_on_panic, SimpleLock};
 
+use crate::thread_pool;
+use crate::time::Driver;
+use crate::waiters::Waiters;
+
 use super::machine;
+use super::metrics::COUNTERS;
 use super::processor::Processor;
+use super::task;
 use super::Task;
 
-/// how long a processor considered to be blocking
-const BLOCKING_THRESHOLD: Duration = Duration::from_millis(10);
+/// in adaptive mode, a task is considered blocking when it run
+/// this many times longer than the p99 poll duration of the processor
+const ADAPTIVE_FACTOR: u32 = 4;
 
 pub struct System {
     /// all processors
     processors: Vec<Processor>,
 
-    /// for blocking detection
-    tick: AtomicU64,
-
     sysmon_parker: SimpleLock<Parker>,
     sysmon_unparker: Unparker,
 
-    processors_parker: Mutex<Parker>,
-    processors_unparker: Unparker,
-    notif_count: CachePadded<AtomicUsize>,
+    /// sysmon is sleeping because there is no task,
+    /// the next push need to wake it
+    sysmon_idle: AtomicBool,
+
+    /// timers are processed by sysmon
+    timer: Driver,
+
+    /// index of sleeping processors
+    idle: Mutex<Vec<usize>>,
+    idle_count: CachePadded<AtomicUsize>,
+
+    /// number of processors that looking for task before sleeping
+    spinning: CachePadded<AtomicUsize>,
+
+    /// tasks waiting for all processors to be idle, see [`wait_idle`],
+    /// the counter is incremented every time they are notified
+    ///
+    /// [`wait_idle`]: ../fn.wait_idle.html
+    idle_waiters: Mutex<(u64, Waiters)>,
 }
 
 // just to make sure
@@ -76,22 +97,23 @@ impl System {
         let sysmon_parker = Parker::new();
         let sysmon_unparker = sysmon_parker.unparker().clone();
 
-        let processors_parker = Parker::new();
-        let processors_unparker = processors_parker.unparker().clone();
-
         // we need fix memory location to pass to Processor::set_system
         // alloc in heap, and leak it
         let system_raw = Box::into_raw(Box::new(System {
             processors,
 
-            tick: AtomicU64::new(0),
+            timer: Driver::new(sysmon_unparker.clone()),
 
             sysmon_parker: SimpleLock::new(sysmon_parker),
             sysmon_unparker,
+            sysmon_idle: AtomicBool::new(false),
+
+            idle: Mutex::new(Vec::with_capacity(num_cpus)),
+            idle_count: CachePadded::new(AtomicUsize::new(0)),
 
-            processors_parker: Mutex::new(processors_parker),
-            processors_unparker,
-            notif_count: CachePadded::new(AtomicUsize::new(0)),
+            spinning: CachePadded::new(AtomicUsize::new(0)),
+
+            idle_waiters: Mutex::new((0, Waiters::new())),
         }));
 
         let system: &'static System = unsafe { &*system_raw };
@@ -116,7 +138,7 @@ impl System {
             let o = others[i].take().unwrap();
             assert_eq!(i, o.0);
 
-            p.set_system(system, o.1);
+            p.set_system(system, i, o.1);
         }
 
         system
@@ -133,28 +155,85 @@ impl System {
         // spawn machine for every processor
         self.processors.iter().for_each(machine::spawn);
 
+        // last schedtick of each processor, and when it is changed
+        let mut seen: Vec<(u64, Instant)> = self
+            .processors
+            .iter()
+            .map(|p| (p.schedtick(), Instant::now()))
+            .collect();
+
+        let mut delay = sysmon_interval().0;
+
         loop {
-            let check_tick = self.tick.fetch_add(1, Ordering::Relaxed) + 1;
+            self.timer.sleep_until(Instant::now() + delay);
+
+            let (min_delay, max_delay) = sysmon_interval();
 
-            thread::sleep(BLOCKING_THRESHOLD);
+            let has_task = !self.is_empty();
 
-            if !self.is_empty() {
-                for p in &self.processors {
-                    if p.get_last_seen() < check_tick {
-                        #[cfg(feature = "tracing")]
-                        trace!("{:?} is blocked, spawn new machine for it", p);
+            if has_task || self.is_running() {
+                let now = Instant::now();
 
-                        machine::spawn(p);
+                // nothing is blocked, back off
+                let mut next_delay = std::cmp::min(delay * 2, max_delay);
+
+                for (p, seen) in self.processors.iter().zip(seen.iter_mut()) {
+                    let schedtick = p.schedtick();
+                    if schedtick != seen.0 {
+                        *seen = (schedtick, now);
+                        continue;
+                    }
+
+                    // not running any task
+                    if schedtick % 2 == 0 {
+                        continue;
+                    }
+
+                    let threshold = blocking_threshold(p);
+                    let running = now - seen.1;
+                    if running >= threshold {
+                        // no need to spawn new machine if there is no other task to run
+                        if has_task {
+                            if machine::handoff(p) {
+                                #[cfg(feature = "tracing")]
+                                trace!("{:?} is blocked, spawn new machine for it", p);
+
+                                next_delay = min_delay;
+                            }
+
+                            // don't check again until it reach the threshold again
+                            seen.1 = now;
+                        }
+                    } else {
+                        // the same task is still running, check again when it reach the threshold
+                        next_delay = std::cmp::min(
+                            next_delay,
+                            std::cmp::max(threshold - running, min_delay),
+                        );
                     }
                 }
+
+                delay = next_delay;
             } else {
-                #[cfg(feature = "tracing")]
-                trace!("Sysmon entering sleep");
+                self.sysmon_idle.store(true, Ordering::Relaxed);
 
-                parker.park();
+                // pair with the fence in push
+                atomic::fence(Ordering::SeqCst);
 
-                #[cfg(feature = "tracing")]
-                trace!("Sysmon exiting sleep");
+                // check again, the pusher may not see sysmon_idle
+                if self.is_empty() && !self.is_running() {
+                    #[cfg(feature = "tracing")]
+                    trace!("Sysmon entering sleep");
+
+                    self.timer.park(&parker);
+
+                    #[cfg(feature = "tracing")]
+                    trace!("Sysmon exiting sleep");
+                }
+
+                self.sysmon_idle.store(false, Ordering::Relaxed);
+
+                delay = min_delay;
             }
         }
     }
@@ -164,54 +243,277 @@ impl System {
         self.processors.iter().all(|p| p.is_empty())
     }
 
+    /// take a processor that is waiting for new machine
     #[inline(always)]
-    pub fn processors_wait_notif(&self) {
-        let mut lock: Option<MutexGuard<Parker>> = None;
-        loop {
-            let notif_count = self.notif_count.load(Ordering::Relaxed);
-            if notif_count > 0 {
-                if self.notif_count.compare_and_swap(
-                    notif_count,
-                    notif_count - 1,
-                    Ordering::Relaxed,
-                ) == notif_count
-                {
-                    drop(lock);
-                    break;
+    pub fn processors_claim(&'static self) -> Option<&'static Processor> {
+        self.processors.iter().find(|p| p.claim())
+    }
+
+    /// is there any processor running a task
+    #[inline(always)]
+    fn is_running(&self) -> bool {
+        self.processors.iter().any(|p| p.schedtick() % 2 == 1)
+    }
+
+    /// no task in the queues, and no task is running,
+    /// including the tasks that are left blocking on their own machine after handoff
+    #[inline(always)]
+    pub fn is_idle(&self) -> bool {
+        self.is_empty()
+            && !self.is_running()
+            && COUNTERS.blocked_machines.load(Ordering::SeqCst) == 0
+    }
+
+    #[inline(always)]
+    fn notify_idle(&self) {
+        let mut waiters = self.idle_waiters.lock().unwrap();
+        if waiters.1.len() > 0 {
+            waiters.0 = waiters.0.wrapping_add(1);
+
+            // we are holding the processor, pushing to its local queue would deadlock
+            machine::detached(|| waiters.1.notify_all());
+        }
+    }
+
+    /// return true if the system is idle, or it has been idle since the first call,
+    /// otherwise register the waker, `epoch` and `key` are the state of the waiter
+    #[inline(always)]
+    pub fn poll_idle(
+        &self,
+        epoch: &mut Option<u64>,
+        key: &mut Option<usize>,
+        waker: &Waker,
+    ) -> bool {
+        let mut waiters = self.idle_waiters.lock().unwrap();
+        match *epoch {
+            None => {
+                if self.is_idle() {
+                    return true;
                 }
-                std::sync::atomic::spin_loop_hint();
-            } else {
-                match lock.as_ref() {
-                    Some(parker) => parker.park(),
-                    None => lock = Some(self.processors_parker.lock().unwrap()),
+                epoch.replace(waiters.0);
+            }
+            Some(e) => {
+                if e != waiters.0 {
+                    return true;
                 }
             }
         }
+        waiters.1.register(key, waker);
+        false
     }
 
     #[inline(always)]
-    pub fn processors_send_notif(&self) {
+    pub fn remove_idle_waiter(&self, key: usize) {
+        self.idle_waiters.lock().unwrap().1.remove(key);
+    }
+
+    /// sleep until `p` is woken via [`processors_wake`] or [`processors_wake_one`],
+    /// return true if `p` is woken as spinning processor
+    ///
+    /// [`processors_wake`]: #method.processors_wake
+    /// [`processors_wake_one`]: #method.processors_wake_one
+    #[inline(always)]
+    pub fn processors_wait(&self, p: &Processor) -> bool {
+        {
+            let mut idle = self.idle.lock().unwrap();
+            idle.push(p.index());
+            self.idle_count.store(idle.len(), Ordering::Relaxed);
+        }
+
+        // pair with the fence in processors_wake_one,
+        // either we see the new task, or the pusher see us in the idle list
+        atomic::fence(Ordering::SeqCst);
+
+        if !self.has_task_for(p) {
+            // we are the last one
+            if self.idle_count.load(Ordering::Relaxed) == self.processors.len() && self.is_idle() {
+                self.notify_idle();
+            }
+
+            p.park();
+        }
+
+        // we are still in the list if we are not woken by others
+        let mut idle = self.idle.lock().unwrap();
+        if let Some(i) = idle.iter().position(|&i| i == p.index()) {
+            idle.swap_remove(i);
+            self.idle_count.store(idle.len(), Ordering::Relaxed);
+        }
+        p.take_woken_spinning()
+    }
+
+    /// is there any task that `p` can run
+    #[inline(always)]
+    fn has_task_for(&self, p: &Processor) -> bool {
+        p.has_pinned() || self.processors.iter().any(|p| p.has_stealable())
+    }
+
+    /// return false if there are already too many spinning processors,
+    /// call [`processors_stop_spinning`] when done
+    ///
+    /// [`processors_stop_spinning`]: #method.processors_stop_spinning
+    #[inline(always)]
+    pub fn processors_start_spinning(&self) -> bool {
+        let max = max_spinning(self.processors.len());
         loop {
-            let notif_count = self.notif_count.load(Ordering::Relaxed);
-            if notif_count >= self.processors.len() {
-                break;
+            let spinning = self.spinning.load(Ordering::Relaxed);
+            if spinning >= max {
+                return false;
             }
             if self
-                .notif_count
-                .compare_and_swap(notif_count, notif_count + 1, Ordering::Relaxed)
-                == notif_count
+                .spinning
+                .compare_exchange(spinning, spinning + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok()
+            {
+                return true;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    /// return true if it is the last spinning processor
+    #[inline(always)]
+    pub fn processors_stop_spinning(&self) -> bool {
+        self.spinning.fetch_sub(1, Ordering::SeqCst) == 1
+    }
+
+    /// wake one sleeping processor, prefer `hint` if it is sleeping,
+    /// the woken processor start as spinning processor, just like `wakep` in golang
+    ///
+    /// do nothing if there is a spinning processor, it will find the task,
+    /// and wake another one when it does, so many tasks wake many processors one by one
+    #[inline(always)]
+    pub fn processors_wake_one(&self, hint: *const Processor) {
+        // pair with the fence in processors_wait,
+        // the spinning processor also go there after it stop spinning
+        atomic::fence(Ordering::SeqCst);
+
+        if self.spinning.load(Ordering::Relaxed) > 0 {
+            return;
+        }
+
+        // fast path, no need to lock when nobody is sleeping
+        if self.idle_count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        // spin on behalf of the processor we are going to wake,
+        // unless spinning is disabled
+        let spinning = max_spinning(self.processors.len()) > 0;
+        if spinning
+            && self
+                .spinning
+                .compare_exchange(0, 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+
+        let p = {
+            let mut idle = self.idle.lock().unwrap();
+            let i = match idle
+                .iter()
+                .position(|&i| ptr::eq(&self.processors[i], hint))
             {
-                self.processors_unparker.unpark();
-                break;
+                Some(i) => Some(idle.swap_remove(i)),
+                None => idle.pop(),
+            };
+            self.idle_count.store(idle.len(), Ordering::Relaxed);
+
+            // before the lock is released, processors_wait read it after locking
+            if let (true, Some(i)) = (spinning, i) {
+                self.processors[i].set_woken_spinning();
+            }
+            i
+        };
+
+        match p {
+            Some(p) => {
+                #[cfg(feature = "tracing")]
+                trace!("Waking {:?}", self.processors[p]);
+
+                self.processors[p].unpark();
+            }
+
+            // all of them are awake, they will find the task
+            None => {
+                if spinning {
+                    self.processors_stop_spinning();
+                }
             }
-            std::sync::atomic::spin_loop_hint();
         }
     }
 
+    /// wake `p` if it is sleeping
     #[inline(always)]
-    pub fn push(&self, task: Task) {
-        match machine::direct_push(task) {
-            Ok(()) => {}
+    fn processors_wake(&self, p: &Processor) {
+        // pair with the fence in processors_wait
+        atomic::fence(Ordering::SeqCst);
+
+        if self.idle_count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let mut idle = self.idle.lock().unwrap();
+        if let Some(i) = idle.iter().position(|&i| i == p.index()) {
+            idle.swap_remove(i);
+            self.idle_count.store(idle.len(), Ordering::Relaxed);
+            drop(idle);
+            p.unpark();
+        }
+    }
+
+    /// push the task, if `next` is true and it is pushed to the local queue,
+    /// it will run next, before the tasks that are already in the queue
+    #[inline(always)]
+    pub fn push(&self, task: Task, next: bool) {
+        if let Some(wake) = self.push_quiet(task, next) {
+            self.notify(wake);
+        }
+    }
+
+    /// push all the tasks, but only notify once,
+    /// the woken processor wake another one when it find a task, and so on
+    #[inline(always)]
+    pub fn push_batch(&self, tasks: impl IntoIterator<Item = Task>) {
+        let mut notify = None;
+        for task in tasks {
+            if let Some(wake) = self.push_quiet(task, false) {
+                notify.get_or_insert(wake);
+            }
+        }
+        if let Some(wake) = notify {
+            self.notify(wake);
+        }
+    }
+
+    /// push without waking anyone, return who should be woken
+    #[inline(always)]
+    fn push_quiet(&self, task: Task, next: bool) -> Option<Wake> {
+        if task.tag().is_pinned() {
+            let p = task.tag().processor_hint();
+            unsafe { &*p }.push_pinned(task);
+            return Some(Wake::Only(p));
+        }
+
+        // don't let it block the processor again
+        if task.tag().is_chronic_blocking() {
+            #[cfg(feature = "tracing")]
+            trace!("{:?} is pushed to the thread pool", task.tag());
+
+            thread_pool::spawn_box(Box::new(move || abort_on_panic(move || task::run(task))));
+            return None;
+        }
+
+        let pushed = if task.tag().take_yield_to_global() {
+            Err(task)
+        } else {
+            machine::direct_push(task, next)
+        };
+
+        match pushed {
+            // we are busy running the current task, let others steal it
+            Ok(p) => Some(Wake::Prefer(p)),
             Err(task) => {
                 let mut p = task.tag().processor_hint();
                 if p.is_null() {
@@ -219,19 +521,76 @@ impl System {
                 }
 
                 unsafe { &*p }.push_global(task);
+                Some(Wake::Prefer(p))
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn notify(&self, wake: Wake) {
+        let p = match wake {
+            Wake::Prefer(p) => {
+                self.processors_wake_one(p);
+                unsafe { &*p }
+            }
+            Wake::Only(p) => {
+                let p = unsafe { &*p };
+                self.processors_wake(p);
+                p
             }
+        };
+
+        // the fence in processors_wake or processors_wake_one
+        // pair with the fence in machine::enter_blocking,
+        // either the blocking machine see the new task, or we see that it is blocking
+        if p.is_blocking() {
+            machine::handoff(p);
         }
 
-        self.sysmon_unparker.unpark();
-        self.processors_send_notif();
+        // also pair with the fence in sysmon_run,
+        // either sysmon see the new task, or we see that sysmon is sleeping
+        if self.sysmon_idle.load(Ordering::Relaxed)
+            && self.sysmon_idle.swap(false, Ordering::Relaxed)
+        {
+            self.sysmon_unparker.unpark();
+        }
     }
 
     #[inline(always)]
-    pub fn now(&self) -> u64 {
-        self.tick.load(Ordering::Relaxed)
+    pub fn processors(&self) -> &[Processor] {
+        &self.processors
+    }
+
+    /// # Panic
+    ///
+    /// When `index` is out of bound
+    #[inline(always)]
+    pub fn processor(&self, index: usize) -> &Processor {
+        assert!(
+            index < self.processors.len(),
+            "processor index {} is out of bound, num_cpus is {}",
+            index,
+            self.processors.len()
+        );
+        &self.processors[index]
+    }
+
+    #[inline(always)]
+    pub fn timer(&self) -> &Driver {
+        &self.timer
     }
 }
 
+/// who should be woken after a push
+#[derive(Clone, Copy)]
+enum Wake {
+    /// any processor, but this one is preferred
+    Prefer(*const Processor),
+
+    /// pinned task, only this processor can run it
+    Only(*const Processor),
+}
+
 #[inline(always)]
 pub fn get() -> &'static System {
     static SYSTEM: (AtomicPtr<System>, Once) = (AtomicPtr::new(ptr::null_mut()), Once::new());
@@ -247,6 +606,12 @@ pub fn get() -> &'static System {
 ///
 /// this is useful if you know that you are going to do blocking that longer
 /// than blocking threshold.
+///
+/// Prefer [`blocking`], the processor is handed off to a new machine right away,
+/// even when nothing need it, and this thread leave the executor after the current poll,
+/// unless it is done before the new machine take over.
+///
+/// [`blocking`]: ../fn.blocking.html
 #[inline(always)]
 pub fn detach_current_thread() {
     machine::respawn();
@@ -254,6 +619,25 @@ pub fn detach_current_thread() {
 
 static NUM_CPUS: AtomicUsize = AtomicUsize::new(0);
 
+/// `usize::MAX` mean default, half of num_cpus
+static MAX_SPINNING: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+static SPIN_DURATION_NS: AtomicU64 = AtomicU64::new(50_000);
+
+static CHRONIC_BLOCKING_THRESHOLD: AtomicUsize = AtomicUsize::new(3);
+
+static BLOCKING_THRESHOLD_NS: (AtomicU64, AtomicU64) =
+    (AtomicU64::new(10_000_000), AtomicU64::new(10_000_000));
+
+static BLOCKING_THRESHOLD_ADAPTIVE: AtomicBool = AtomicBool::new(false);
+
+static MAX_BLOCKED_MACHINES: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+static MEASURE_POLL_TIME: AtomicBool = AtomicBool::new(false);
+
+static SYSMON_INTERVAL_NS: (AtomicU64, AtomicU64) =
+    (AtomicU64::new(20_000), AtomicU64::new(10_000_000));
+
 /// Set the number of executor thread
 ///
 /// Analogous to `GOMAXPROCS` in golang,
@@ -261,11 +645,9 @@ static NUM_CPUS: AtomicUsize = AtomicUsize::new(0);
 /// if not set before executor running, it will be the number of available cpu in the host
 #[inline(always)]
 pub fn set_num_cpus(size: usize) -> Result<(), String> {
-    let old_value = NUM_CPUS.compare_and_swap(0, size, Ordering::Relaxed);
-    if old_value == 0 {
-        Ok(())
-    } else {
-        Err(format!("num_cpus already set to {}", old_value))
+    match NUM_CPUS.compare_exchange(0, size, Ordering::Relaxed, Ordering::Relaxed) {
+        Ok(_) => Ok(()),
+        Err(old_value) => Err(format!("num_cpus already set to {}", old_value)),
     }
 }
 
@@ -282,11 +664,256 @@ pub fn get_num_cpus() -> Option<usize> {
     }
 }
 
+/// Set the maximum number of processors that keep looking for task before sleeping
+///
+/// Just like spinning M in golang, a processor that run out of task will keep
+/// looking for task for a while (see [`set_spin_duration`]) before sleeping,
+/// so new task can be picked up without waiting for the processor to wake up.
+/// A new task doesn't wake a sleeping processor when there is a spinning one,
+/// the woken processor is spinning until it find a task, then it wake the next one.
+///
+/// Default to half of [`get_num_cpus`], set to 0 to disable spinning.
+///
+/// [`set_spin_duration`]: fn.set_spin_duration.html
+/// [`get_num_cpus`]: fn.get_num_cpus.html
+#[inline(always)]
+pub fn set_max_spinning(max: usize) {
+    MAX_SPINNING.store(std::cmp::min(max, usize::MAX - 1), Ordering::Relaxed);
+}
+
+/// Set how long a processor keep looking for task before sleeping
+///
+/// Default to 50µs, see [`set_max_spinning`]
+///
+/// [`set_max_spinning`]: fn.set_max_spinning.html
+#[inline(always)]
+pub fn set_spin_duration(duration: Duration) {
+    let ns = std::cmp::min(duration.as_nanos(), u64::MAX as u128) as u64;
+    SPIN_DURATION_NS.store(ns, Ordering::Relaxed);
+}
+
+/// Set the range of blocking threshold
+///
+/// A processor is considered blocking when the same task is running longer than
+/// the threshold, sysmon will spawn new machine to take over the processor.
+///
+/// When `min` is equal to `max`, the threshold is fixed.
+/// Otherwise, the threshold is adaptive, the poll duration of every processor is measured,
+/// and the threshold is 4 times its p99 poll duration, bounded by `min` and `max`,
+/// so short poll workload detect blocking sooner, and long poll workload doesn't spawn
+/// new machine needlessly. See [`metrics`] for the current value.
+///
+/// Default to fixed 10ms.
+///
+/// # Panic
+///
+/// When `min` is zero or greater than `max`
+///
+/// [`metrics`]: fn.metrics.html
+#[inline(always)]
+pub fn set_blocking_threshold(min: Duration, max: Duration) {
+    assert!(
+        min > Duration::from_secs(0),
+        "min blocking threshold must be non-zero"
+    );
+    assert!(
+        min <= max,
+        "min blocking threshold must not be greater than max"
+    );
+
+    let to_ns = |d: Duration| std::cmp::min(d.as_nanos(), u64::MAX as u128) as u64;
+    BLOCKING_THRESHOLD_NS.0.store(to_ns(min), Ordering::Relaxed);
+    BLOCKING_THRESHOLD_NS.1.store(to_ns(max), Ordering::Relaxed);
+    BLOCKING_THRESHOLD_ADAPTIVE.store(min != max, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn is_blocking_threshold_adaptive() -> bool {
+    BLOCKING_THRESHOLD_ADAPTIVE.load(Ordering::Relaxed)
+}
+
+/// the current blocking threshold of the processor
+#[inline(always)]
+pub fn blocking_threshold(p: &Processor) -> Duration {
+    let min = Duration::from_nanos(BLOCKING_THRESHOLD_NS.0.load(Ordering::Relaxed));
+    let max = Duration::from_nanos(BLOCKING_THRESHOLD_NS.1.load(Ordering::Relaxed));
+
+    if !is_blocking_threshold_adaptive() {
+        return max;
+    }
+
+    match p.poll_percentile(99) {
+        // nothing is measured yet, be conservative
+        None => max,
+        Some(p99) => std::cmp::min(std::cmp::max(p99 * ADAPTIVE_FACTOR, min), max),
+    }
+}
+
+/// Set the maximum number of blocked machines
+///
+/// A machine is blocked when its processor is taken over by new machine
+/// because it is blocking (see [`set_blocking_threshold`] and [`blocking`]),
+/// every blocked machine is one extra thread.
+///
+/// When the limit is reached, sysmon stop spawning new machine, the blocking task
+/// keep blocking its processor, and [`try_spawn`] fail until some of them come back,
+/// see [`metrics`] for how often it happen.
+///
+/// Default to unlimited.
+///
+/// [`set_blocking_threshold`]: fn.set_blocking_threshold.html
+/// [`blocking`]: ../fn.blocking.html
+/// [`try_spawn`]: ../fn.try_spawn.html
+/// [`metrics`]: fn.metrics.html
+#[inline(always)]
+pub fn set_max_blocked_machines(max: usize) {
+    MAX_BLOCKED_MACHINES.store(max, Ordering::Relaxed);
+}
+
+/// is the number of blocked machines reached the limit
+#[inline(always)]
+pub fn is_blocked_machines_full() -> bool {
+    COUNTERS.blocked_machines.load(Ordering::Relaxed)
+        >= MAX_BLOCKED_MACHINES.load(Ordering::Relaxed)
+}
+
+/// return false if the limit is reached
+#[inline(always)]
+pub fn add_blocked_machine() -> bool {
+    let max = MAX_BLOCKED_MACHINES.load(Ordering::Relaxed);
+    let added = COUNTERS
+        .blocked_machines
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+            if n < max {
+                Some(n + 1)
+            } else {
+                None
+            }
+        })
+        .is_ok();
+
+    if !added {
+        COUNTERS
+            .blocked_machines_limit_hits
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    added
+}
+
+#[inline(always)]
+pub fn remove_blocked_machine() {
+    // the processors may be already sleeping, no one else will notice that it is idle now
+    if COUNTERS.blocked_machines.fetch_sub(1, Ordering::SeqCst) == 1 {
+        // pair with the fence in processors_wait,
+        // either we see that the last processor is sleeping, or it see that we are done
+        atomic::fence(Ordering::SeqCst);
+
+        let system = get();
+        if system.is_idle() {
+            system.notify_idle();
+        }
+    }
+}
+
+/// Measure the duration of every poll, for [`TaskStats::poll_time`]
+///
+/// Disabled by default, reading the clock twice for every poll is not free.
+///
+/// [`TaskStats::poll_time`]: struct.TaskStats.html#structfield.poll_time
+#[inline(always)]
+pub fn set_measure_poll_time(enabled: bool) {
+    MEASURE_POLL_TIME.store(enabled, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn is_poll_time_measured() -> bool {
+    MEASURE_POLL_TIME.load(Ordering::Relaxed)
+}
+
+/// Set how many times a task can block before it is moved to the thread pool
+///
+/// Every time sysmon detect that a task is blocking, a new machine is spawned to
+/// take over the processor, when the same task keep doing it (e.g. using synchronous
+/// database driver), it is cheaper to run it in the thread pool like [`blocking`],
+/// the task never run on the processors again.
+///
+/// Default to 3, set to 0 to disable, see also [`metrics`].
+///
+/// [`blocking`]: ../fn.blocking.html
+/// [`metrics`]: fn.metrics.html
+#[inline(always)]
+pub fn set_chronic_blocking_threshold(n: usize) {
+    CHRONIC_BLOCKING_THRESHOLD.store(n, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn chronic_blocking_threshold() -> usize {
+    CHRONIC_BLOCKING_THRESHOLD.load(Ordering::Relaxed)
+}
+
+/// Set the range of sysmon interval
+///
+/// Sysmon is the thread that detect blocking task, it check the processors every interval,
+/// the interval start from `min`, doubled every time nothing is blocked, up to `max`,
+/// and back to `min` when there is a blocked processor.
+///
+/// Sysmon doesn't wake up at all when there is no task.
+///
+/// Default to 20µs and 10ms, like sysmon in golang.
+///
+/// # Panic
+///
+/// When `min` is zero or greater than `max`
+#[inline(always)]
+pub fn set_sysmon_interval(min: Duration, max: Duration) {
+    assert!(
+        min > Duration::from_secs(0),
+        "min sysmon interval must be non-zero"
+    );
+    assert!(
+        min <= max,
+        "min sysmon interval must not be greater than max"
+    );
+
+    let to_ns = |d: Duration| std::cmp::min(d.as_nanos(), u64::MAX as u128) as u64;
+    SYSMON_INTERVAL_NS.0.store(to_ns(min), Ordering::Relaxed);
+    SYSMON_INTERVAL_NS.1.store(to_ns(max), Ordering::Relaxed);
+}
+
+#[inline(always)]
+fn sysmon_interval() -> (Duration, Duration) {
+    let min = SYSMON_INTERVAL_NS.0.load(Ordering::Relaxed);
+    let max = SYSMON_INTERVAL_NS.1.load(Ordering::Relaxed);
+    (
+        Duration::from_nanos(min),
+        Duration::from_nanos(std::cmp::max(min, max)),
+    )
+}
+
+#[inline(always)]
+fn max_spinning(num_cpus: usize) -> usize {
+    match MAX_SPINNING.load(Ordering::Relaxed) {
+        usize::MAX => num_cpus / 2,
+        max => max,
+    }
+}
+
+#[inline(always)]
+pub fn spin_duration() -> Duration {
+    Duration::from_nanos(SPIN_DURATION_NS.load(Ordering::Relaxed))
+}
+
 #[inline(always)]
 fn lock_num_cpus() -> usize {
     let num_cpus = &NUM_CPUS;
     if num_cpus.load(Ordering::Relaxed) == 0 {
-        num_cpus.compare_and_swap(0, std::cmp::max(1, num_cpus::get()), Ordering::Relaxed);
+        let _ = num_cpus.compare_exchange(
+            0,
+            std::cmp::max(1, num_cpus::get()),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
     }
     num_cpus.load(Ordering::Relaxed)
 }
@@ -304,3 +931,271 @@ fn gcd(a: usize, b: usize) -> usize {
     }
     p.0
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+
+    use super::*;
+
+    /// run `f` in a new process with `num_cpus` processors, the settings are global
+    fn fresh_runtime(name: &str, num_cpus: usize, f: impl FnOnce()) {
+        crate::fresh_runtime::__run_in_fresh_runtime(module_path!(), name, || {
+            set_num_cpus(num_cpus).unwrap();
+            f();
+        });
+    }
+
+    fn wait_for(cond: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !cond() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn idle_processors(system: &System) -> Vec<usize> {
+        let mut idle = system.idle.lock().unwrap().clone();
+        idle.sort_unstable();
+        idle
+    }
+
+    #[test]
+    fn idle_list() {
+        fresh_runtime("idle_list", 4, || {
+            let system = get();
+
+            for _ in 0..10 {
+                // each of them is woken via its own parker
+                let handles: Vec<_> = (0..4)
+                    .map(|i| crate::spawn_on(i, async move { i }))
+                    .collect();
+                for (i, handle) in handles.into_iter().enumerate() {
+                    assert_eq!(crate::block_on(handle), i);
+                }
+
+                // all of them are sleeping, and listed once
+                crate::block_until_idle();
+                wait_for(|| system.idle_count.load(Ordering::Relaxed) == 4);
+                assert_eq!(idle_processors(system), [0, 1, 2, 3]);
+            }
+        });
+    }
+
+    #[test]
+    fn spinning() {
+        fresh_runtime("spinning", 4, || {
+            let system = get();
+
+            // the max spinning processors that the tasks see
+            let run = |n: usize| {
+                let max = Arc::new(AtomicUsize::new(0));
+                let handles: Vec<_> = (0..n)
+                    .map(|_| {
+                        let max = max.clone();
+                        crate::spawn(async move {
+                            max.fetch_max(system.spinning.load(Ordering::SeqCst), Ordering::SeqCst);
+                            crate::yield_now().await;
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    crate::block_on(handle);
+                }
+                max.load(Ordering::SeqCst)
+            };
+
+            // default to half of the processors
+            set_spin_duration(Duration::from_millis(1));
+            for _ in 0..10 {
+                assert!(run(100) <= 2);
+            }
+
+            // all of them stop spinning before sleeping
+            crate::block_until_idle();
+            wait_for(|| system.idle_count.load(Ordering::Relaxed) == 4);
+            assert_eq!(system.spinning.load(Ordering::SeqCst), 0);
+
+            // disabled, the tasks still run
+            set_max_spinning(0);
+            for _ in 0..10 {
+                assert_eq!(run(100), 0);
+            }
+            crate::block_until_idle();
+            wait_for(|| system.idle_count.load(Ordering::Relaxed) == 4);
+            assert_eq!(system.spinning.load(Ordering::SeqCst), 0);
+        });
+    }
+
+    #[test]
+    fn spawn_batch_wake() {
+        fresh_runtime("spawn_batch_wake", 4, || {
+            let system = get();
+
+            for _ in 0..10 {
+                // nothing to do, sysmon is sleeping too
+                crate::block_until_idle();
+                wait_for(|| system.idle_count.load(Ordering::Relaxed) == 4);
+                wait_for(|| system.sysmon_idle.load(Ordering::Relaxed));
+
+                // only one processor is woken for the batch,
+                // it wake the next one when it find a task, and so on
+                let used = Arc::new(Mutex::new(Vec::new()));
+                let handles = crate::spawn_batch((0..8).map(|_| {
+                    let used = used.clone();
+                    async move {
+                        machine::with_current_task(|tag| {
+                            let index = unsafe { &*tag.processor_hint() }.index();
+                            used.lock().unwrap().push(index);
+                        });
+
+                        // not long enough to be detected as blocking
+                        thread::sleep(Duration::from_millis(2));
+                    }
+                }));
+                for handle in handles {
+                    crate::block_on(handle);
+                }
+
+                let mut used = used.lock().unwrap().clone();
+                assert_eq!(used.len(), 8);
+                used.sort_unstable();
+                used.dedup();
+                assert!(used.len() > 1, "only {:?} is woken", used);
+            }
+        });
+    }
+
+    /// keep the processor busy with other task until the returned flag is set,
+    /// so sysmon hand off the blocked processor
+    fn keep_busy() -> Arc<AtomicBool> {
+        let done = Arc::new(AtomicBool::new(false));
+        crate::spawn({
+            let done = done.clone();
+            async move {
+                while !done.load(Ordering::Relaxed) {
+                    crate::yield_now().await;
+                }
+            }
+        });
+        done
+    }
+
+    fn is_on_processor() -> bool {
+        let mut on = false;
+        machine::with_current_task(|_| on = true);
+        on
+    }
+
+    #[test]
+    fn chronic_blocking() {
+        fresh_runtime("chronic_blocking", 1, || {
+            set_blocking_threshold(Duration::from_millis(1), Duration::from_millis(1));
+            set_chronic_blocking_threshold(2);
+
+            let done = keep_busy();
+            let on_processor = crate::block_on(crate::spawn(async {
+                let mut on_processor = Vec::new();
+                for _ in 0..5 {
+                    on_processor.push(is_on_processor());
+                    thread::sleep(Duration::from_millis(50));
+                    crate::yield_now().await;
+                }
+                on_processor
+            }));
+            done.store(true, Ordering::Relaxed);
+            crate::block_until_idle();
+
+            // moved to the thread pool after the second incident
+            assert_eq!(on_processor, [true, true, false, false, false]);
+            let metrics = crate::metrics();
+            assert_eq!(metrics.blocking_incidents, 2);
+            assert_eq!(metrics.chronic_blocking_tasks, 1);
+            assert_eq!(metrics.blocked_machines, 0);
+        });
+    }
+
+    #[test]
+    fn adaptive_blocking_threshold() {
+        fresh_runtime("adaptive_blocking_threshold", 1, || {
+            let max = Duration::from_secs(10);
+            set_blocking_threshold(Duration::from_millis(1), max);
+
+            // nothing is measured yet
+            let metrics = crate::metrics();
+            assert_eq!(metrics.processors[0].blocking_threshold, max);
+            assert_eq!(metrics.processors[0].poll_p99, None);
+
+            let handles: Vec<_> = (0..1000).map(|_| crate::spawn(async {})).collect();
+            for handle in handles {
+                crate::block_on(handle);
+            }
+
+            let metrics = crate::metrics();
+            let p99 = metrics.processors[0].poll_p99.unwrap();
+            let threshold = metrics.processors[0].blocking_threshold;
+            assert!(threshold >= Duration::from_millis(1));
+            assert!(threshold < max);
+            assert_eq!(threshold, std::cmp::max(p99 * 4, Duration::from_millis(1)));
+
+            // detected long before the max
+            let done = keep_busy();
+            crate::block_on(crate::spawn(async {
+                thread::sleep(Duration::from_millis(200));
+            }));
+            done.store(true, Ordering::Relaxed);
+            crate::block_until_idle();
+
+            assert_eq!(crate::metrics().blocking_incidents, 1);
+        });
+    }
+
+    #[test]
+    fn max_blocked_machines() {
+        fresh_runtime("max_blocked_machines", 1, || {
+            set_max_blocked_machines(1);
+            let p = get().processor(0);
+
+            // block in blocking section until the sender is used
+            let spawn_blocked = || {
+                let (tx, rx) = mpsc::channel::<()>();
+                let started = Arc::new(AtomicBool::new(false));
+                let handle = crate::spawn({
+                    let started = started.clone();
+                    async move {
+                        started.store(true, Ordering::SeqCst);
+                        crate::blocking(|| rx.recv().unwrap());
+                    }
+                });
+                (tx, started, handle)
+            };
+
+            let (tx_a, a_started, a) = spawn_blocked();
+            wait_for(|| a_started.load(Ordering::SeqCst) && p.is_blocking());
+
+            // a is blocked, b take over the processor, and block it too
+            let (tx_b, b_started, b) = spawn_blocked();
+            wait_for(|| b_started.load(Ordering::SeqCst) && p.is_blocking());
+            assert_eq!(crate::metrics().blocked_machines, 1);
+            assert!(crate::try_spawn(async {}).is_err());
+
+            // the limit is reached, c have to wait
+            let c = crate::spawn(async {});
+            thread::sleep(Duration::from_millis(50));
+            assert!(crate::metrics().blocked_machines_limit_hits > 0);
+            assert!(!p.is_empty());
+
+            // a is back, so b can be handed off for c
+            tx_a.send(()).unwrap();
+            crate::block_on(a);
+            crate::block_on(c);
+
+            tx_b.send(()).unwrap();
+            crate::block_on(b);
+            crate::block_until_idle();
+
+            assert_eq!(crate::metrics().blocked_machines, 0);
+            assert!(crate::try_spawn(async {}).is_ok());
+        });
+    }
+}
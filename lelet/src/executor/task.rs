@@ -1,33 +1,83 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
 use std::ptr;
-use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::task::Poll;
+use std::time::{Duration, Instant};
 
-use super::processor::Processor;
+use crate::context::Context;
 
-#[cfg(feature = "tracing")]
-use std::sync::atomic::AtomicUsize;
+use super::machine;
+use super::metrics::COUNTERS;
+use super::processor::Processor;
+use super::system;
+use super::Task;
 
 #[cfg(feature = "tracing")]
 use log::trace;
 
+thread_local! {
+    /// the task that is being polled in this thread
+    static POLLING: Cell<*const TaskTag> = const { Cell::new(ptr::null()) };
+}
+
 pub struct TaskTag {
-    #[cfg(feature = "tracing")]
     id: usize,
 
     processor_hint: AtomicPtr<Processor>,
+
+    /// only run on the processor in `processor_hint`
+    pinned: bool,
+
+    /// the next push go to the global queue
+    yield_to_global: AtomicBool,
+
+    /// context owned by this task, canceled when the task is canceled
+    context: Option<Context>,
+
+    /// number of times this task is detected blocking by sysmon
+    blocking_incidents: AtomicUsize,
+
+    /// this task keep blocking, run it in the thread pool instead of processor
+    chronic_blocking: AtomicBool,
+
+    /// see [`TaskStats`]
+    ///
+    /// [`TaskStats`]: struct.TaskStats.html
+    polls: AtomicU64,
+    poll_time_ns: AtomicU64,
+    wakes: AtomicU64,
+
+    /// the future returned its output
+    finished: AtomicBool,
 }
 
 impl TaskTag {
     #[inline(always)]
-    pub fn new() -> TaskTag {
-        #[cfg(feature = "tracing")]
+    pub fn new(context: Option<Context>) -> TaskTag {
         static TASK_ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
         #[allow(clippy::let_and_return)]
         let tag = TaskTag {
-            #[cfg(feature = "tracing")]
             id: TASK_ID_COUNTER.fetch_add(1, Ordering::Relaxed),
 
             processor_hint: AtomicPtr::new(ptr::null_mut()),
+
+            pinned: false,
+
+            yield_to_global: AtomicBool::new(false),
+
+            context,
+
+            blocking_incidents: AtomicUsize::new(0),
+            chronic_blocking: AtomicBool::new(false),
+
+            polls: AtomicU64::new(0),
+            poll_time_ns: AtomicU64::new(0),
+            wakes: AtomicU64::new(0),
+
+            finished: AtomicBool::new(false),
         };
 
         #[cfg(feature = "tracing")]
@@ -36,6 +86,11 @@ impl TaskTag {
         tag
     }
 
+    #[inline(always)]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
     #[inline(always)]
     pub fn processor_hint(&self) -> *const Processor {
         self.processor_hint.load(Ordering::Relaxed)
@@ -46,6 +101,158 @@ impl TaskTag {
         self.processor_hint
             .store(processor as *mut _, Ordering::Relaxed);
     }
+
+    #[inline(always)]
+    pub fn pin_to(&mut self, processor: &Processor) {
+        self.set_processor_hint(processor);
+        self.pinned = true;
+    }
+
+    #[inline(always)]
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    #[inline(always)]
+    pub fn set_yield_to_global(&self) {
+        self.yield_to_global.store(true, Ordering::Relaxed);
+    }
+
+    /// return true if the flag was set, and clear it
+    #[inline(always)]
+    pub fn take_yield_to_global(&self) -> bool {
+        // avoid the store in common case
+        self.yield_to_global.load(Ordering::Relaxed)
+            && self.yield_to_global.swap(false, Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    pub fn context(&self) -> Option<&Context> {
+        self.context.as_ref()
+    }
+
+    /// called when sysmon spawn new machine because this task is blocking,
+    /// mark it as chronic blocking when it reach the threshold
+    #[inline(always)]
+    pub fn add_blocking_incident(&self) {
+        COUNTERS.blocking_incidents.fetch_add(1, Ordering::Relaxed);
+
+        let n = self.blocking_incidents.fetch_add(1, Ordering::Relaxed) + 1;
+        let threshold = system::chronic_blocking_threshold();
+        if threshold == 0 || n < threshold {
+            return;
+        }
+
+        if !self.chronic_blocking.swap(true, Ordering::Relaxed) {
+            COUNTERS
+                .chronic_blocking_tasks
+                .fetch_add(1, Ordering::Relaxed);
+
+            #[cfg(feature = "tracing")]
+            trace!(
+                "{:?} is blocking {} times, it will run in the thread pool",
+                self,
+                n
+            );
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_chronic_blocking(&self) -> bool {
+        self.chronic_blocking.load(Ordering::Relaxed)
+    }
+
+    /// called when the task is woken
+    #[inline(always)]
+    pub fn add_wake(&self) {
+        self.wakes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// only called by the one who poll the task
+    #[inline(always)]
+    fn add_poll(&self, duration: Option<Duration>, finished: bool) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        if let Some(duration) = duration {
+            let ns = std::cmp::min(duration.as_nanos(), u64::MAX as u128) as u64;
+            self.poll_time_ns.fetch_add(ns, Ordering::Relaxed);
+        }
+        if finished {
+            self.finished.store(true, Ordering::Release);
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+
+    #[inline(always)]
+    pub fn stats(&self) -> TaskStats {
+        TaskStats {
+            polls: self.polls.load(Ordering::Relaxed),
+            poll_time: Duration::from_nanos(self.poll_time_ns.load(Ordering::Relaxed)),
+            wakes: self.wakes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Statistics of a task, returned by [`JoinHandle::stats`]
+///
+/// [`JoinHandle::stats`]: struct.JoinHandle.html#method.stats
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct TaskStats {
+    /// number of times the task is polled
+    pub polls: u64,
+
+    /// total duration of all the polls,
+    /// only measured when enabled via [`set_measure_poll_time`]
+    ///
+    /// [`set_measure_poll_time`]: fn.set_measure_poll_time.html
+    pub poll_time: Duration,
+
+    /// number of times the task is woken, not including the first schedule when it is spawned
+    pub wakes: u64,
+}
+
+/// run the task, so [`TaskFuture`] know which task it is
+///
+/// [`TaskFuture`]: struct.TaskFuture.html
+#[inline(always)]
+pub fn run(task: Task) {
+    let tag = task.tag() as *const TaskTag;
+    let old = POLLING.with(|polling| polling.replace(tag));
+    task.run();
+    POLLING.with(|polling| polling.set(old));
+}
+
+/// the future of every task, to observe each poll while the tag is still alive
+pub struct TaskFuture<F>(pub F);
+
+impl<F: Future> Future for TaskFuture<F> {
+    type Output = F::Output;
+
+    #[inline(always)]
+    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context) -> Poll<F::Output> {
+        // the future is never moved
+        let future = unsafe { self.map_unchecked_mut(|this| &mut this.0) };
+
+        let tag = POLLING.with(|polling| polling.get());
+        let start = if system::is_poll_time_measured() {
+            Some(Instant::now())
+        } else {
+            None
+        };
+
+        let poll = machine::observe_poll(|| future.poll(cx));
+
+        // still safe, we are inside the poll of the task
+        if let Some(tag) = unsafe { tag.as_ref() } {
+            tag.add_poll(start.map(|s| s.elapsed()), poll.is_ready());
+        }
+
+        poll
+    }
 }
 
 #[cfg(feature = "tracing")]
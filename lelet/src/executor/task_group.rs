@@ -0,0 +1,450 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use crate::waiters::Waiters;
+
+use super::JoinHandle;
+
+/// Group of task
+///
+/// All the task in the group will be canceled when the group is dropped.
+pub struct TaskGroup<R> {
+    /// indexed by key, `None` when the slot is free
+    handles: Vec<Option<Slot<R>>>,
+    free: Vec<usize>,
+    len: usize,
+
+    ready: Arc<Ready>,
+    limit: Option<Arc<Limit>>,
+}
+
+struct Slot<R> {
+    handle: JoinHandle<R>,
+
+    /// push the key to `ready` when woken
+    waker: Waker,
+}
+
+impl<R: Send + 'static> TaskGroup<R> {
+    /// Create new empty group
+    #[inline(always)]
+    pub fn new() -> TaskGroup<R> {
+        TaskGroup {
+            handles: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+            ready: Arc::new(Ready::new()),
+            limit: None,
+        }
+    }
+
+    /// Create new empty group, with at most `limit` task running at once
+    ///
+    /// The rest of the task will wait until the running one is done
+    ///
+    /// # Panic
+    ///
+    /// Panic if `limit` is 0
+    #[inline(always)]
+    pub fn with_limit(limit: usize) -> TaskGroup<R> {
+        assert!(limit > 0, "limit must be greater than 0");
+
+        let mut group = TaskGroup::new();
+        group.limit = Some(Arc::new(Limit::new(limit)));
+        group
+    }
+
+    /// Run the task in the background as part of this group
+    #[inline(always)]
+    pub fn spawn<T>(&mut self, task: T)
+    where
+        T: Future<Output = R> + Send + 'static,
+    {
+        let handle = match &self.limit {
+            None => super::spawn(task),
+            Some(limit) => {
+                let acquire = Acquire {
+                    limit: limit.clone(),
+                    key: None,
+                };
+                super::spawn(async move {
+                    let _permit = acquire.await;
+                    task.await
+                })
+            }
+        };
+
+        let key = self.free.pop().unwrap_or(self.handles.len());
+        let slot = Slot {
+            handle,
+            waker: Waker::from(Arc::new(ReadyWaker {
+                key,
+                ready: self.ready.clone(),
+            })),
+        };
+        if key == self.handles.len() {
+            self.handles.push(Some(slot));
+        } else {
+            self.handles[key] = Some(slot);
+        }
+        self.len += 1;
+
+        // poll it once, so it can register the waker
+        self.ready.push(key);
+    }
+
+    /// Wait for the next task to be done, in the order of completion
+    ///
+    /// Return `None` if the group is empty
+    #[inline(always)]
+    pub fn join_next(&mut self) -> JoinNext<'_, R> {
+        JoinNext(self)
+    }
+
+    /// Number of task in the group that not joined yet
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return `true` if there is no task in the group
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Cancel all the task in the group
+    #[inline(always)]
+    pub fn cancel_all(&mut self) {
+        self.handles
+            .drain(..)
+            .flatten()
+            .for_each(|slot| slot.handle.cancel());
+        self.free.clear();
+        self.len = 0;
+        self.ready.clear();
+    }
+}
+
+impl<R: Send + 'static> Default for TaskGroup<R> {
+    #[inline(always)]
+    fn default() -> TaskGroup<R> {
+        TaskGroup::new()
+    }
+}
+
+impl<R> Drop for TaskGroup<R> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.handles
+            .drain(..)
+            .flatten()
+            .for_each(|slot| slot.handle.cancel());
+    }
+}
+
+/// Future returned by [`TaskGroup::join_next`]
+///
+/// [`TaskGroup::join_next`]: struct.TaskGroup.html#method.join_next
+pub struct JoinNext<'a, R>(&'a mut TaskGroup<R>);
+
+impl<R> Future for JoinNext<'_, R> {
+    type Output = Option<R>;
+
+    #[inline(always)]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let group = &mut *self.0;
+
+        if group.len == 0 {
+            return Poll::Ready(None);
+        }
+
+        // only poll the tasks that are woken, not all of them
+        while let Some(key) = group.ready.pop(cx.waker()) {
+            // the key can be stale (already joined), just skip it
+            let slot = match group.handles[key].as_mut() {
+                Some(slot) => slot,
+                None => continue,
+            };
+
+            let mut task_cx = Context::from_waker(&slot.waker);
+            if let Poll::Ready(val) = Pin::new(&mut slot.handle).poll(&mut task_cx) {
+                group.handles[key] = None;
+                group.free.push(key);
+                group.len -= 1;
+                return Poll::Ready(Some(val));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+/// keys of the tasks that need to be polled
+struct Ready {
+    state: Mutex<ReadyState>,
+}
+
+struct ReadyState {
+    keys: VecDeque<usize>,
+    waker: Option<Waker>,
+}
+
+impl Ready {
+    #[inline(always)]
+    fn new() -> Ready {
+        Ready {
+            state: Mutex::new(ReadyState {
+                keys: VecDeque::new(),
+                waker: None,
+            }),
+        }
+    }
+
+    #[inline(always)]
+    fn push(&self, key: usize) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.keys.push_back(key);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// return `None` if there is no ready key, `waker` will be woken on the next push
+    #[inline(always)]
+    fn pop(&self, waker: &Waker) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        let key = state.keys.pop_front();
+        if key.is_none() {
+            match &state.waker {
+                Some(w) if w.will_wake(waker) => {}
+                _ => state.waker = Some(waker.clone()),
+            }
+        }
+        key
+    }
+
+    #[inline(always)]
+    fn clear(&self) {
+        self.state.lock().unwrap().keys.clear();
+    }
+}
+
+struct ReadyWaker {
+    key: usize,
+    ready: Arc<Ready>,
+}
+
+impl Wake for ReadyWaker {
+    #[inline(always)]
+    fn wake(self: Arc<Self>) {
+        self.ready.push(self.key);
+    }
+
+    #[inline(always)]
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.push(self.key);
+    }
+}
+
+struct Limit {
+    max: usize,
+    state: Mutex<LimitState>,
+}
+
+struct LimitState {
+    running: usize,
+    waiters: Waiters,
+}
+
+impl Limit {
+    #[inline(always)]
+    fn new(max: usize) -> Limit {
+        Limit {
+            max,
+            state: Mutex::new(LimitState {
+                running: 0,
+                waiters: Waiters::new(),
+            }),
+        }
+    }
+}
+
+struct Acquire {
+    limit: Arc<Limit>,
+    key: Option<usize>,
+}
+
+impl Future for Acquire {
+    type Output = Permit;
+
+    #[inline(always)]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Permit> {
+        let limit = self.limit.clone();
+        let mut state = limit.state.lock().unwrap();
+
+        if state.running < limit.max {
+            if let Some(key) = self.key.take() {
+                state.waiters.remove(key);
+            }
+            state.running += 1;
+            return Poll::Ready(Permit(limit.clone()));
+        }
+
+        state.waiters.register(&mut self.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut state = self.limit.state.lock().unwrap();
+
+            // we already notified, pass it to the next waiter
+            if !state.waiters.remove(key) && state.running < self.limit.max {
+                state.waiters.notify_one();
+            }
+        }
+    }
+}
+
+struct Permit(Arc<Limit>);
+
+impl Drop for Permit {
+    #[inline(always)]
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.running -= 1;
+        state.waiters.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// increment the counter when dropped
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn join_order() {
+        let mut group = TaskGroup::new();
+        let mut senders = Vec::new();
+        for i in 0..3 {
+            let (tx, rx) = crate::chan::bounded(1);
+            senders.push(tx);
+            group.spawn(async move {
+                rx.recv().await.unwrap();
+                i
+            });
+        }
+        assert_eq!(group.len(), 3);
+
+        // in the order of completion, not the order of spawn
+        crate::block_on(async {
+            for &i in &[2, 0, 1] {
+                senders[i].send(()).await.unwrap();
+                assert_eq!(group.join_next().await, Some(i));
+            }
+            assert_eq!(group.join_next().await, None);
+        });
+        assert!(group.is_empty());
+    }
+
+    #[test]
+    fn cancel_all() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+
+        let mut group = TaskGroup::new();
+        for _ in 0..10 {
+            let guard = DropCounter(dropped.clone());
+            group.spawn(async move {
+                let _guard = guard;
+                pending::<()>().await
+            });
+        }
+
+        group.cancel_all();
+        assert!(group.is_empty());
+        assert_eq!(crate::block_on(group.join_next()), None);
+
+        crate::block_until_idle();
+        assert_eq!(dropped.load(Ordering::SeqCst), 10);
+
+        // still usable
+        group.spawn(async {});
+        assert_eq!(crate::block_on(group.join_next()), Some(()));
+    }
+
+    #[test]
+    fn cancel_on_drop() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+
+        let mut group = TaskGroup::with_limit(2);
+        for _ in 0..10 {
+            // some of them are still waiting for the limit
+            let guard = DropCounter(dropped.clone());
+            group.spawn(async move {
+                let _guard = guard;
+                pending::<()>().await
+            });
+        }
+        drop(group);
+
+        crate::block_until_idle();
+        assert_eq!(dropped.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn limit() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max = Arc::new(AtomicUsize::new(0));
+
+        let mut group = TaskGroup::with_limit(3);
+        for _ in 0..20 {
+            let running = running.clone();
+            let max = max.clone();
+            group.spawn(async move {
+                let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max.fetch_max(n, Ordering::SeqCst);
+                crate::yield_now().await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+
+        let joined = crate::block_on(async {
+            let mut joined = 0;
+            while group.join_next().await.is_some() {
+                joined += 1;
+            }
+            joined
+        });
+
+        assert_eq!(joined, 20);
+        assert!(max.load(Ordering::SeqCst) <= 3);
+        assert_eq!(running.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "limit must be greater than 0")]
+    fn zero_limit() {
+        TaskGroup::<()>::with_limit(0);
+    }
+}
@@ -0,0 +1,62 @@
+//! Support for `#[lelet::test(fresh_runtime)]`
+//!
+//! The executor is global to the process, so a fresh runtime is a fresh process:
+//! the test binary run itself again with only the test selected,
+//! and the test body is executed in that child process.
+
+use std::env;
+use std::ffi::OsStr;
+use std::process::Command;
+
+/// set in the child process to the name of the test that it should run
+const ENV_KEY: &str = "__LELET_FRESH_RUNTIME";
+
+/// run `f` (the body of the test) in a new process of the current test binary
+///
+/// `module_path` and `name` are used to select the test, so it must be called
+/// directly from the test function
+#[doc(hidden)]
+pub fn __run_in_fresh_runtime(module_path: &str, name: &str, f: impl FnOnce()) {
+    // libtest doesn't include the crate name
+    let name = match module_path.find("::") {
+        Some(i) => format!("{}::{}", &module_path[i + 2..], name),
+        None => name.to_string(),
+    };
+
+    // we are the child
+    if env::var_os(ENV_KEY).as_deref() == Some(OsStr::new(&name)) {
+        f();
+        return;
+    }
+
+    let exe = env::current_exe().expect("fresh_runtime: cannot get the path of the test binary");
+    let output = Command::new(exe)
+        .args([
+            name.as_str(),
+            "--exact",
+            "--include-ignored",
+            "--test-threads=1",
+            "--nocapture",
+        ])
+        .env(ENV_KEY, &name)
+        .output()
+        .expect("fresh_runtime: cannot run the test binary");
+
+    // forward it, so it is captured by the test harness like the normal test
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    print!("{}", stdout);
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+
+    assert!(
+        output.status.success(),
+        "test {} failed on fresh runtime",
+        name
+    );
+
+    // the test is not selected, i.e. the name is not what libtest expect
+    assert!(
+        stdout.contains("test result: ok. 1 passed"),
+        "test {} is not run on fresh runtime",
+        name
+    );
+}
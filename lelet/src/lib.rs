@@ -9,26 +9,65 @@
 //! create thread via [`thread::spawn`], and the number of thread you can create
 //! is not unlimited, so the number of blocking task you can [`spawn`] is also not unlimited
 //!
+//! If you know that the code is going to block, wrap it in [`blocking`],
+//! so the other tasks don't need to wait for the detection.
+//!
 //! [`thread::spawn`]: https://doc.rust-lang.org/std/thread/fn.spawn.html
 //! [`spawn`]: fn.spawn.html
+//! [`blocking`]: fn.blocking.html
 
 #[doc(hidden)]
 pub mod thread_pool;
 
+pub mod chan;
+pub mod context;
+pub mod sync;
+pub mod time;
+
 mod executor;
+mod waiters;
+
+#[cfg(any(test, feature = "macros"))]
+mod fresh_runtime;
+
 pub use executor::spawn;
+pub use executor::spawn_batch;
+pub use executor::try_spawn;
 pub use executor::JoinHandle;
+pub use executor::TaskStats;
+pub use executor::{spawn_near, spawn_on};
+pub use executor::{yield_now, yield_to_global};
+
+pub use executor::{blocking, BlockingGuard};
+
+pub use executor::{block_until_idle, wait_idle, WaitIdle};
+
+pub use executor::{scope, Scope};
+
+pub use executor::{JoinNext, TaskGroup};
+
+pub use context::Context;
+
+pub use executor::{metrics, Metrics, ProcessorMetrics};
 
 pub use executor::get_num_cpus;
+pub use executor::set_blocking_threshold;
+pub use executor::set_chronic_blocking_threshold;
+pub use executor::set_max_blocked_machines;
+pub use executor::set_measure_poll_time;
 pub use executor::set_num_cpus;
+pub use executor::set_sysmon_interval;
+pub use executor::{set_max_spinning, set_spin_duration};
 
-pub use lelet_utils::block_on;
+pub use executor::block_on;
+pub use executor::{run, run_with, ExitPolicy};
 
+#[cfg(feature = "macros")]
+pub use lelet_macros::{main, test};
+
+#[cfg(feature = "macros")]
 #[doc(hidden)]
-pub use executor::detach_current_thread;
+pub use fresh_runtime::__run_in_fresh_runtime;
 
 #[doc(hidden)]
-#[inline(always)]
-pub async fn yield_now() {
-    lelet_utils::Yields(1).await;
-}
+pub use executor::detach_current_thread;
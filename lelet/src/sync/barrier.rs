@@ -0,0 +1,107 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use crate::waiters::Waiters;
+
+/// Barrier for `n` task
+///
+/// All the task will wait until `n` task have called [`wait`].
+///
+/// [`wait`]: #method.wait
+pub struct Barrier {
+    n: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    count: usize,
+    generation: usize,
+    waiters: Waiters,
+}
+
+impl Barrier {
+    /// Create new barrier for `n` task
+    #[inline(always)]
+    pub fn new(n: usize) -> Barrier {
+        Barrier {
+            n,
+            state: Mutex::new(State {
+                count: 0,
+                generation: 0,
+                waiters: Waiters::new(),
+            }),
+        }
+    }
+
+    /// Wait until all the task have called this
+    ///
+    /// The task is counted when the returned future is polled for the first time,
+    /// dropping the future after that still counted as waiting.
+    #[inline(always)]
+    pub fn wait(&self) -> BarrierWait<'_> {
+        BarrierWait {
+            barrier: self,
+            generation: None,
+            key: None,
+        }
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Barrier").field("n", &self.n).finish()
+    }
+}
+
+/// Future returned by [`Barrier::wait`]
+///
+/// [`Barrier::wait`]: struct.Barrier.html#method.wait
+pub struct BarrierWait<'a> {
+    barrier: &'a Barrier,
+    generation: Option<usize>,
+    key: Option<usize>,
+}
+
+impl Future for BarrierWait<'_> {
+    /// `true` for exactly one task (the leader) on every generation
+    type Output = bool;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<bool> {
+        let this = &mut *self;
+        let mut state = this.barrier.state.lock().unwrap();
+
+        match this.generation {
+            None => {
+                state.count += 1;
+                if state.count >= this.barrier.n {
+                    state.count = 0;
+                    state.generation = state.generation.wrapping_add(1);
+                    state.waiters.notify_all();
+                    return Poll::Ready(true);
+                }
+                this.generation = Some(state.generation);
+            }
+
+            Some(generation) if generation != state.generation => {
+                this.key = None;
+                return Poll::Ready(false);
+            }
+
+            Some(_) => {}
+        }
+
+        state.waiters.register(&mut this.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for BarrierWait<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.barrier.state.lock().unwrap().waiters.remove(key);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Async synchronization primitives
+//!
+//! Waiting on these primitives will park the task instead of the thread,
+//! so it is safe to hold the lock across `.await`, the executor will not see it as blocking.
+
+mod barrier;
+mod mutex;
+mod notify;
+mod once_cell;
+mod rwlock;
+mod semaphore;
+
+pub use barrier::{Barrier, BarrierWait};
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::{Notified, Notify};
+pub use once_cell::OnceCell;
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Acquire, Semaphore, SemaphorePermit};
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Poll;
+
+    use super::*;
+
+    #[test]
+    fn mutex() {
+        let m = Arc::new(Mutex::new(0));
+
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let m = m.clone();
+                crate::spawn(async move {
+                    for _ in 0..100 {
+                        let mut guard = m.lock().await;
+                        let val = *guard;
+                        crate::yield_now().await;
+                        *guard = val + 1;
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(crate::block_on);
+
+        let guard = m.try_lock().unwrap();
+        assert_eq!(*guard, 1000);
+        assert!(m.try_lock().is_none());
+    }
+
+    #[test]
+    fn rwlock() {
+        let lock = RwLock::new(1);
+
+        let r1 = lock.try_read().unwrap();
+        let r2 = lock.try_read().unwrap();
+        assert_eq!(*r1 + *r2, 2);
+        assert!(lock.try_write().is_none());
+        drop((r1, r2));
+
+        let mut w = lock.try_write().unwrap();
+        *w = 2;
+        assert!(lock.try_read().is_none());
+        drop(w);
+
+        assert_eq!(crate::block_on(async { *lock.read().await }), 2);
+    }
+
+    #[test]
+    fn semaphore() {
+        let sem = Arc::new(Semaphore::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let sem = sem.clone();
+                let running = running.clone();
+                crate::spawn(async move {
+                    let _permit = sem.acquire().await;
+                    assert!(running.fetch_add(1, Ordering::SeqCst) < 2);
+                    crate::yield_now().await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(crate::block_on);
+
+        assert_eq!(sem.available_permits(), 2);
+        let permit = sem.try_acquire_many(2).unwrap();
+        assert!(sem.try_acquire().is_none());
+        permit.forget();
+        assert_eq!(sem.available_permits(), 0);
+    }
+
+    #[test]
+    fn notify() {
+        let notify = Arc::new(Notify::new());
+
+        // the permit is stored
+        notify.notify_one();
+        crate::block_on(notify.notified());
+
+        let handle = crate::spawn({
+            let notify = notify.clone();
+            async move { notify.notified().await }
+        });
+        crate::block_on(crate::wait_idle());
+        notify.notify_one();
+        crate::block_on(handle);
+    }
+
+    #[test]
+    fn notify_dropped() {
+        let notify = Notify::new();
+
+        // poll the future once, return true if it is ready
+        let poll_once = |f: &mut Pin<Box<Notified>>| {
+            crate::block_on(crate::chan::__poll_fn(|cx| {
+                Poll::Ready(f.as_mut().poll(cx).is_ready())
+            }))
+        };
+
+        // woken by notify_waiters and dropped before polled, no permit is left
+        let mut notified = Box::pin(notify.notified());
+        assert!(!poll_once(&mut notified));
+        notify.notify_waiters();
+        drop(notified);
+        assert!(!poll_once(&mut Box::pin(notify.notified())));
+
+        // woken by notify_one and dropped before polled, the permit is passed on
+        let mut notified = Box::pin(notify.notified());
+        assert!(!poll_once(&mut notified));
+        notify.notify_one();
+        drop(notified);
+        assert!(poll_once(&mut Box::pin(notify.notified())));
+    }
+
+    #[test]
+    fn barrier() {
+        let barrier = Arc::new(Barrier::new(5));
+
+        let handles: Vec<_> = (0..5)
+            .map(|_| {
+                let barrier = barrier.clone();
+                crate::spawn(async move { barrier.wait().await })
+            })
+            .collect();
+        let leaders = handles
+            .into_iter()
+            .map(crate::block_on)
+            .filter(|&leader| leader)
+            .count();
+        assert_eq!(leaders, 1);
+    }
+
+    #[test]
+    fn once_cell() {
+        let cell = OnceCell::new();
+        let calls = AtomicUsize::new(0);
+
+        crate::block_on(async {
+            for _ in 0..3 {
+                let val = cell
+                    .get_or_init(|| async {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                        1
+                    })
+                    .await;
+                assert_eq!(*val, 1);
+            }
+        });
+
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert_eq!(cell.set(2), Err(2));
+        assert_eq!(cell.into_inner(), Some(1));
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use crate::waiters::Waiters;
+
+/// Notify a task to wake up
+///
+/// When there is no task waiting, [`notify_one`] will store a permit,
+/// so the next [`notified`] will complete immediately.
+///
+/// [`notify_one`]: #method.notify_one
+/// [`notified`]: #method.notified
+pub struct Notify {
+    state: Mutex<State>,
+}
+
+struct State {
+    permit: bool,
+    waiters: Waiters,
+
+    /// keys of the waiters woken by `notify_one` that are not done yet,
+    /// only these notifications are passed on when the waiter is dropped
+    notified: Vec<usize>,
+}
+
+impl State {
+    #[inline(always)]
+    fn notify_one(&mut self) {
+        match self.waiters.notify_first() {
+            Some(key) => self.notified.push(key),
+            None => self.permit = true,
+        }
+    }
+
+    /// the waiter is done with the notification,
+    /// return true if the notification came from `notify_one`
+    #[inline(always)]
+    fn take_notified(&mut self, key: usize) -> bool {
+        match self.notified.iter().position(|&k| k == key) {
+            Some(i) => {
+                self.notified.swap_remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Notify {
+    /// Create new Notify without permit
+    #[inline(always)]
+    pub fn new() -> Notify {
+        Notify {
+            state: Mutex::new(State {
+                permit: false,
+                waiters: Waiters::new(),
+                notified: Vec::new(),
+            }),
+        }
+    }
+
+    /// Wait for notification
+    #[inline(always)]
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            key: None,
+        }
+    }
+
+    /// Wake the first waiting task, or store a permit if there is no waiting task
+    #[inline(always)]
+    pub fn notify_one(&self) {
+        self.state.lock().unwrap().notify_one();
+    }
+
+    /// Wake all the waiting task, no permit is stored
+    #[inline(always)]
+    pub fn notify_waiters(&self) {
+        self.state.lock().unwrap().waiters.notify_all();
+    }
+}
+
+impl Default for Notify {
+    #[inline(always)]
+    fn default() -> Notify {
+        Notify::new()
+    }
+}
+
+impl fmt::Debug for Notify {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Notify { .. }")
+    }
+}
+
+/// Future returned by [`Notify::notified`]
+///
+/// [`Notify::notified`]: struct.Notify.html#method.notified
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    key: Option<usize>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = &mut *self;
+        let mut state = this.notify.state.lock().unwrap();
+
+        match this.key {
+            Some(key) if !state.waiters.contains(key) => {
+                this.key = None;
+                state.take_notified(key);
+                Poll::Ready(())
+            }
+
+            None if state.permit => {
+                state.permit = false;
+                Poll::Ready(())
+            }
+
+            _ => {
+                state.waiters.register(&mut this.key, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut state = self.notify.state.lock().unwrap();
+
+            // we already notified by `notify_one`, pass it to the next waiter,
+            // the notification from `notify_waiters` is only for the current waiters
+            if !state.waiters.remove(key) && state.take_notified(key) {
+                state.notify_one();
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::Semaphore;
+
+/// A cell that can be written only once
+///
+/// Only one task run the initialization on [`get_or_init`],
+/// the other task will wait for it without blocking the thread.
+///
+/// [`get_or_init`]: #method.get_or_init
+pub struct OnceCell<T> {
+    initialized: AtomicBool,
+    sem: Semaphore,
+    value: UnsafeCell<Option<T>>,
+}
+
+unsafe impl<T: Send> Send for OnceCell<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    /// Create new empty cell
+    #[inline(always)]
+    pub fn new() -> OnceCell<T> {
+        OnceCell {
+            initialized: AtomicBool::new(false),
+            sem: Semaphore::new(1),
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    /// Get the value, `None` if the cell is not initialized yet
+    #[inline(always)]
+    pub fn get(&self) -> Option<&T> {
+        if self.initialized.load(Ordering::Acquire) {
+            unsafe { (*self.value.get()).as_ref() }
+        } else {
+            None
+        }
+    }
+
+    /// Set the value, return it back if the cell is already initialized
+    /// (or being initialized)
+    #[inline(always)]
+    pub fn set(&self, value: T) -> Result<(), T> {
+        match self.sem.try_acquire() {
+            Some(_permit) if !self.initialized.load(Ordering::Acquire) => {
+                unsafe { self.init(value) };
+                Ok(())
+            }
+            _ => Err(value),
+        }
+    }
+
+    /// Get the value, or initialize it with `f` if the cell is not initialized yet
+    ///
+    /// If the future returned by `f` is dropped before it is done,
+    /// other task will run the initialization.
+    #[inline(always)]
+    pub async fn get_or_init<F, Fut>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        let _permit = self.sem.acquire().await;
+
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        unsafe { self.init(f().await) }
+    }
+
+    /// Consumes the cell, returning the value
+    #[inline(always)]
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+
+    /// the caller must hold the permit, and the cell is not initialized yet
+    #[inline(always)]
+    unsafe fn init(&self, value: T) -> &T {
+        *self.value.get() = Some(value);
+        self.initialized.store(true, Ordering::Release);
+        (*self.value.get()).as_ref().unwrap()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    #[inline(always)]
+    fn default() -> OnceCell<T> {
+        OnceCell::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+            None => f.write_str("OnceCell(<uninit>)"),
+        }
+    }
+}
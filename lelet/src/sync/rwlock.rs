@@ -0,0 +1,160 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use super::Semaphore;
+
+/// maximum number of concurrent reader
+const MAX_READS: usize = usize::MAX >> 3;
+
+/// Async reader-writer lock
+///
+/// The lock is fair, a waiting writer will block the reader that come after it,
+/// so the writer will not starve.
+pub struct RwLock<T: ?Sized> {
+    sem: Semaphore,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// Returns a new RwLock initialized with `value`.
+    #[inline(always)]
+    pub fn new(value: T) -> RwLock<T> {
+        RwLock {
+            sem: Semaphore::new(MAX_READS),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Consumes the lock, returning the underlying data.
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Lock with shared read access, wait until there is no writer
+    #[inline(always)]
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.sem.acquire().await.forget();
+        RwLockReadGuard(self)
+    }
+
+    /// Lock with exclusive write access, wait until there is no reader or writer
+    #[inline(always)]
+    pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.sem.acquire_many(MAX_READS).await.forget();
+        RwLockWriteGuard(self)
+    }
+
+    /// Try to lock with shared read access.
+    #[inline(always)]
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        self.sem.try_acquire().map(|permit| {
+            permit.forget();
+            RwLockReadGuard(self)
+        })
+    }
+
+    /// Try to lock with exclusive write access.
+    #[inline(always)]
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.sem.try_acquire_many(MAX_READS).map(|permit| {
+            permit.forget();
+            RwLockWriteGuard(self)
+        })
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.value.get() }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    #[inline(always)]
+    fn default() -> RwLock<T> {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => f.debug_tuple("RwLock").field(&&*guard).finish(),
+            None => f.write_str("RwLock(<locked>)"),
+        }
+    }
+}
+
+/// A guard holding shared read access of [`RwLock`].
+///
+/// [`RwLock`]: struct.RwLock.html
+pub struct RwLockReadGuard<'a, T: ?Sized>(&'a RwLock<T>);
+
+unsafe impl<T: ?Sized + Sync> Send for RwLockReadGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.0.sem.release(1);
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.0.value.get() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// A guard holding exclusive write access of [`RwLock`].
+///
+/// [`RwLock`]: struct.RwLock.html
+pub struct RwLockWriteGuard<'a, T: ?Sized>(&'a RwLock<T>);
+
+unsafe impl<T: ?Sized + Send + Sync> Send for RwLockWriteGuard<'_, T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.0.sem.release(MAX_READS);
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        unsafe { &*self.0.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0.value.get() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
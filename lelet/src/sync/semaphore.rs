@@ -0,0 +1,233 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+/// Async semaphore
+///
+/// Waiting task is served in FIFO order, a task that need many permits will block
+/// the task behind it, even if there is enough permits for them.
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+struct State {
+    permits: usize,
+    waiters: VecDeque<Waiter>,
+    next_key: usize,
+}
+
+struct Waiter {
+    key: usize,
+    needed: usize,
+    waker: Waker,
+
+    /// the permits is already assigned to this waiter
+    granted: bool,
+}
+
+impl State {
+    /// assign the permits to the waiters in FIFO order
+    #[inline(always)]
+    fn grant(&mut self) {
+        for w in self.waiters.iter_mut().filter(|w| !w.granted) {
+            if w.needed > self.permits {
+                break;
+            }
+            self.permits -= w.needed;
+            w.granted = true;
+            w.waker.wake_by_ref();
+        }
+    }
+
+    #[inline(always)]
+    fn release(&mut self, n: usize) {
+        self.permits += n;
+        self.grant();
+    }
+
+    #[inline(always)]
+    fn has_waiting(&self) -> bool {
+        self.waiters.iter().any(|w| !w.granted)
+    }
+}
+
+impl Semaphore {
+    /// Create new semaphore with `permits` permits
+    #[inline(always)]
+    pub fn new(permits: usize) -> Semaphore {
+        Semaphore {
+            state: Mutex::new(State {
+                permits,
+                waiters: VecDeque::new(),
+                next_key: 0,
+            }),
+        }
+    }
+
+    /// Number of available permits
+    #[inline(always)]
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().permits
+    }
+
+    /// Add `n` permits
+    #[inline(always)]
+    pub fn add_permits(&self, n: usize) {
+        self.state.lock().unwrap().release(n);
+    }
+
+    /// Acquire a permit, wait if there is no available permit
+    #[inline(always)]
+    pub fn acquire(&self) -> Acquire<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Acquire `n` permits, wait if there is not enough available permits
+    #[inline(always)]
+    pub fn acquire_many(&self, n: usize) -> Acquire<'_> {
+        Acquire {
+            sem: self,
+            n,
+            key: None,
+        }
+    }
+
+    /// Try to acquire a permit without waiting
+    #[inline(always)]
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Try to acquire `n` permits without waiting
+    #[inline(always)]
+    pub fn try_acquire_many(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if state.has_waiting() || state.permits < n {
+            return None;
+        }
+        state.permits -= n;
+        Some(SemaphorePermit { sem: self, n })
+    }
+
+    #[inline(always)]
+    pub(crate) fn release(&self, n: usize) {
+        self.state.lock().unwrap().release(n);
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.available_permits())
+            .finish()
+    }
+}
+
+/// Future returned by [`Semaphore::acquire`] and [`Semaphore::acquire_many`]
+///
+/// [`Semaphore::acquire`]: struct.Semaphore.html#method.acquire
+/// [`Semaphore::acquire_many`]: struct.Semaphore.html#method.acquire_many
+pub struct Acquire<'a> {
+    sem: &'a Semaphore,
+    n: usize,
+    key: Option<usize>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let sem = self.sem;
+        let n = self.n;
+        let mut state = sem.state.lock().unwrap();
+
+        match self.key {
+            None => {
+                if !state.has_waiting() && state.permits >= n {
+                    state.permits -= n;
+                    return Poll::Ready(SemaphorePermit { sem, n });
+                }
+
+                let key = state.next_key;
+                state.next_key = state.next_key.wrapping_add(1);
+                state.waiters.push_back(Waiter {
+                    key,
+                    needed: n,
+                    waker: cx.waker().clone(),
+                    granted: false,
+                });
+                self.key = Some(key);
+
+                Poll::Pending
+            }
+
+            Some(key) => {
+                let i = state.waiters.iter().position(|w| w.key == key).unwrap();
+
+                if state.waiters[i].granted {
+                    state.waiters.remove(i);
+                    self.key = None;
+                    return Poll::Ready(SemaphorePermit { sem, n });
+                }
+
+                if !state.waiters[i].waker.will_wake(cx.waker()) {
+                    state.waiters[i].waker = cx.waker().clone();
+                }
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut state = self.sem.state.lock().unwrap();
+            let i = state.waiters.iter().position(|w| w.key == key).unwrap();
+            let w = state.waiters.remove(i).unwrap();
+            if w.granted {
+                state.release(w.needed);
+            } else {
+                // the waiter behind us may be able to proceed now
+                state.grant();
+            }
+        }
+    }
+}
+
+/// Permits acquired from [`Semaphore`], the permits are released when dropped
+///
+/// [`Semaphore`]: struct.Semaphore.html
+pub struct SemaphorePermit<'a> {
+    sem: &'a Semaphore,
+    n: usize,
+}
+
+impl SemaphorePermit<'_> {
+    /// Forget the permits, the permits will not be released back to the semaphore
+    #[inline(always)]
+    pub fn forget(mut self) {
+        self.n = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if self.n > 0 {
+            self.sem.release(self.n);
+        }
+    }
+}
+
+impl fmt::Debug for SemaphorePermit<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SemaphorePermit")
+            .field("permits", &self.n)
+            .finish()
+    }
+}
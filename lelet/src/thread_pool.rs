@@ -31,7 +31,7 @@ impl std::fmt::Debug for ThreadID {
 
 #[cfg(feature = "tracing")]
 thread_local! {
-  pub static THREAD_ID: ThreadID = ThreadID(Cell::new(usize::MAX));
+  pub static THREAD_ID: ThreadID = const { ThreadID(Cell::new(usize::MAX)) };
 }
 
 type Job = Box<dyn FnOnce() + Send>;
@@ -101,11 +101,15 @@ impl Pool {
 
                         // only 1 thread is allowed to exit per IDLE_THRESHOLD
                         // ensure it via CAS
-                        if self.next_exit.compare_and_swap(
-                            next_exit,
-                            new_next_exit,
-                            Ordering::Relaxed,
-                        ) == next_exit
+                        if self
+                            .next_exit
+                            .compare_exchange(
+                                next_exit,
+                                new_next_exit,
+                                Ordering::Relaxed,
+                                Ordering::Relaxed,
+                            )
+                            .is_ok()
                         {
                             #[cfg(feature = "tracing")]
                             THREAD_ID.with(|id| {
@@ -0,0 +1,124 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::Waker;
+use std::time::Instant;
+
+use crossbeam_utils::sync::{Parker, Unparker};
+
+use super::wheel::{Entry, Wheel};
+
+/// Drive the timer wheel
+///
+/// The wheel is processed by the sysmon thread, while it is sleeping between
+/// blocking checks ([`sleep_until`]) or while it is parked because there is no task ([`park`]).
+///
+/// [`sleep_until`]: struct.Driver.html#method.sleep_until
+/// [`park`]: struct.Driver.html#method.park
+pub struct Driver {
+    state: Mutex<State>,
+
+    /// to interrupt `sleep_until`
+    cond: Condvar,
+
+    /// to interrupt `park`
+    unparker: Unparker,
+}
+
+struct State {
+    wheel: Wheel,
+
+    /// when the driver will process the wheel again, `None` means never
+    wake_at: Option<Instant>,
+}
+
+impl Driver {
+    #[inline(always)]
+    pub fn new(unparker: Unparker) -> Driver {
+        Driver {
+            state: Mutex::new(State {
+                wheel: Wheel::new(),
+                wake_at: Some(Instant::now()),
+            }),
+            cond: Condvar::new(),
+            unparker,
+        }
+    }
+
+    /// return false if the entry is already expired, it is not registered
+    // Option::is_none_or need rust 1.82
+    #[allow(clippy::unnecessary_map_or)]
+    #[inline(always)]
+    pub fn register(&self, entry: Arc<Entry>) -> bool {
+        let deadline = entry.deadline();
+
+        let mut state = self.state.lock().unwrap();
+        if !state.wheel.insert(entry) {
+            return false;
+        }
+
+        if state.wake_at.map_or(true, |w| deadline < w) {
+            state.wake_at = Some(deadline);
+            drop(state);
+
+            self.cond.notify_one();
+            self.unparker.unpark();
+        }
+
+        true
+    }
+
+    #[inline(always)]
+    pub fn deregister(&self, entry: &Arc<Entry>) {
+        self.state.lock().unwrap().wheel.remove(entry);
+    }
+
+    /// sleep until `target`, while firing the expired timers
+    #[inline(always)]
+    pub fn sleep_until(&self, target: Instant) {
+        loop {
+            let now = Instant::now();
+            self.fire(now);
+            if now >= target {
+                return;
+            }
+
+            let mut state = self.state.lock().unwrap();
+            let wake_at = match state.wheel.next_deadline() {
+                Some(next) if next < target => next,
+                _ => target,
+            };
+            state.wake_at = Some(wake_at);
+
+            let timeout = wake_at.saturating_duration_since(Instant::now());
+            drop(self.cond.wait_timeout(state, timeout).unwrap());
+        }
+    }
+
+    /// park until the next timer expired or unparked, then fire the expired timers
+    #[inline(always)]
+    pub fn park(&self, parker: &Parker) {
+        self.fire(Instant::now());
+
+        let next = {
+            let mut state = self.state.lock().unwrap();
+            let next = state.wheel.next_deadline();
+            state.wake_at = next;
+            next
+        };
+
+        match next {
+            Some(next) => parker.park_timeout(next.saturating_duration_since(Instant::now())),
+            None => parker.park(),
+        }
+
+        self.fire(Instant::now());
+    }
+
+    #[inline(always)]
+    fn fire(&self, now: Instant) {
+        let mut wakers: Vec<Waker> = Vec::new();
+        self.state.lock().unwrap().wheel.process(now, &mut wakers);
+
+        // wake outside the lock
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
@@ -0,0 +1,351 @@
+//! Utilities for tracking time
+//!
+//! The timers are driven by the executor itself, no additional thread is spawned,
+//! the resolution is 1 millisecond, and the timers never fire early.
+
+mod driver;
+mod wheel;
+
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+pub(crate) use driver::Driver;
+
+use wheel::Entry;
+
+/// Wait until `duration` has elapsed
+#[inline(always)]
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(deadline_after(duration))
+}
+
+/// Wait until `deadline` is reached
+#[inline(always)]
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        entry: None,
+    }
+}
+
+/// Future returned by [`sleep`] and [`sleep_until`]
+///
+/// [`sleep`]: fn.sleep.html
+/// [`sleep_until`]: fn.sleep_until.html
+pub struct Sleep {
+    deadline: Instant,
+    entry: Option<Arc<Entry>>,
+}
+
+impl Sleep {
+    /// The instant when this future will complete
+    #[inline(always)]
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Return true if the deadline is reached
+    #[inline(always)]
+    pub fn is_elapsed(&self) -> bool {
+        match self.entry.as_ref() {
+            Some(entry) => entry.is_fired(),
+            None => Instant::now() >= self.deadline,
+        }
+    }
+
+    /// Change the deadline, the future need to be polled again to register the new deadline
+    #[inline(always)]
+    pub fn reset(&mut self, deadline: Instant) {
+        self.cancel();
+        self.deadline = deadline;
+    }
+
+    #[inline(always)]
+    fn cancel(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            if !entry.is_fired() {
+                crate::executor::timer().deregister(&entry);
+            }
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if let Some(entry) = self.entry.as_ref() {
+            if entry.is_fired() {
+                return Poll::Ready(());
+            }
+
+            entry.set_waker(cx.waker());
+
+            // check again, the timer may be fired before we set the waker
+            if entry.is_fired() {
+                return Poll::Ready(());
+            }
+
+            return Poll::Pending;
+        }
+
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        let entry = Entry::new(self.deadline, cx.waker().clone());
+        if !crate::executor::timer().register(entry.clone()) {
+            return Poll::Ready(());
+        }
+        self.entry = Some(entry);
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+impl fmt::Debug for Sleep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sleep")
+            .field("deadline", &self.deadline)
+            .finish()
+    }
+}
+
+/// Require a future to complete before `duration` has elapsed
+///
+/// If the future is not completed in time, [`Elapsed`] is returned and the future is dropped.
+///
+/// [`Elapsed`]: struct.Elapsed.html
+#[inline(always)]
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    timeout_at(deadline_after(duration), future)
+}
+
+/// Require a future to complete before `deadline` is reached
+///
+/// If the future is not completed in time, [`Elapsed`] is returned and the future is dropped.
+///
+/// [`Elapsed`]: struct.Elapsed.html
+#[inline(always)]
+pub fn timeout_at<F: Future>(deadline: Instant, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep_until(deadline),
+    }
+}
+
+/// Future returned by [`timeout`] and [`timeout_at`]
+///
+/// [`timeout`]: fn.timeout.html
+/// [`timeout_at`]: fn.timeout_at.html
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+impl<F> Timeout<F> {
+    /// Get the underlying future back
+    #[inline(always)]
+    pub fn into_inner(self) -> F {
+        self.future
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // the future is never moved
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if let Poll::Ready(v) = unsafe { Pin::new_unchecked(&mut this.future) }.poll(cx) {
+            return Poll::Ready(Ok(v));
+        }
+
+        match Pin::new(&mut this.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Elapsed)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<F> fmt::Debug for Timeout<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Timeout")
+            .field("deadline", &self.sleep.deadline)
+            .finish()
+    }
+}
+
+/// Error returned by [`Timeout`], the deadline has elapsed
+///
+/// [`Timeout`]: struct.Timeout.html
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+/// Create new [`Interval`] that yields every `period`, the first tick completes immediately
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+///
+/// [`Interval`]: struct.Interval.html
+#[inline(always)]
+pub fn interval(period: Duration) -> Interval {
+    interval_at(Instant::now(), period)
+}
+
+/// Create new [`Interval`] that yields every `period`, the first tick completes at `start`
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+///
+/// [`Interval`]: struct.Interval.html
+#[inline(always)]
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(period > Duration::from_secs(0), "period must be non-zero");
+
+    Interval {
+        sleep: sleep_until(start),
+        period,
+        missed_tick_behavior: MissedTickBehavior::default(),
+    }
+}
+
+/// What [`Interval`] should do when a tick is missed
+///
+/// A tick is missed when [`Interval::tick`] is not called in time,
+/// for example because the previous tick took longer than the period to handle.
+///
+/// [`Interval`]: struct.Interval.html
+/// [`Interval::tick`]: struct.Interval.html#method.tick
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MissedTickBehavior {
+    /// Fire the missed ticks as fast as possible until it catches up,
+    /// the ticks stay aligned with the original schedule, this is the default
+    Burst,
+
+    /// Fire the missed tick immediately, then the next ticks are scheduled
+    /// `period` after the time the tick is handled
+    Delay,
+
+    /// Skip the missed ticks, and fire on the next multiple of `period`
+    /// from the original schedule
+    Skip,
+}
+
+impl Default for MissedTickBehavior {
+    #[inline(always)]
+    fn default() -> MissedTickBehavior {
+        MissedTickBehavior::Burst
+    }
+}
+
+/// Stream of ticks, returned by [`interval`] and [`interval_at`]
+///
+/// [`interval`]: fn.interval.html
+/// [`interval_at`]: fn.interval_at.html
+pub struct Interval {
+    sleep: Sleep,
+    period: Duration,
+    missed_tick_behavior: MissedTickBehavior,
+}
+
+impl Interval {
+    /// Wait until the next tick, return the instant when the tick was scheduled
+    #[inline(always)]
+    pub async fn tick(&mut self) -> Instant {
+        crate::chan::__poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    /// Poll version of [`tick`]
+    ///
+    /// [`tick`]: struct.Interval.html#method.tick
+    pub fn poll_tick(&mut self, cx: &mut Context) -> Poll<Instant> {
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let scheduled = self.sleep.deadline();
+        let now = Instant::now();
+
+        let next = match self.missed_tick_behavior {
+            MissedTickBehavior::Burst => scheduled + self.period,
+            MissedTickBehavior::Delay => std::cmp::max(scheduled, now) + self.period,
+            MissedTickBehavior::Skip => {
+                let period = self.period.as_nanos();
+                let missed = now.saturating_duration_since(scheduled).as_nanos() / period;
+                scheduled + Duration::from_nanos(((missed + 1) * period) as u64)
+            }
+        };
+
+        self.sleep.reset(next);
+
+        Poll::Ready(scheduled)
+    }
+
+    /// Reset the interval, the next tick will complete after `period` from now
+    #[inline(always)]
+    pub fn reset(&mut self) {
+        self.sleep.reset(Instant::now() + self.period);
+    }
+
+    /// The period of this interval
+    #[inline(always)]
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// The current [`MissedTickBehavior`]
+    ///
+    /// [`MissedTickBehavior`]: enum.MissedTickBehavior.html
+    #[inline(always)]
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.missed_tick_behavior
+    }
+
+    /// Set the [`MissedTickBehavior`]
+    ///
+    /// [`MissedTickBehavior`]: enum.MissedTickBehavior.html
+    #[inline(always)]
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.missed_tick_behavior = behavior;
+    }
+}
+
+impl fmt::Debug for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Interval")
+            .field("period", &self.period)
+            .field("missed_tick_behavior", &self.missed_tick_behavior)
+            .finish()
+    }
+}
+
+/// `now + duration`, saturated to far future instead of overflow
+#[inline(always)]
+pub(crate) fn deadline_after(duration: Duration) -> Instant {
+    let now = Instant::now();
+    now.checked_add(duration)
+        .unwrap_or_else(|| now + Duration::from_secs(60 * 60 * 24 * 365 * 30))
+}
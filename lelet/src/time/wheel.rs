@@ -0,0 +1,391 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+use std::time::{Duration, Instant};
+
+/// number of bits for slot index in each level
+const LEVEL_BITS: u32 = 6;
+
+/// number of slots in each level
+const SLOTS: usize = 1 << LEVEL_BITS;
+
+/// number of levels, covering 64^6 ms (about 2 years)
+const LEVELS: usize = 6;
+
+/// range of the top level, entry further than that is still put in the top level
+/// (the slot wrap around), and re-inserted when it is processed
+const MAX_MS: u64 = (1 << (LEVEL_BITS as usize * LEVELS)) - 1;
+
+/// location of entry that is not in the wheel
+const NO_LOCATION: usize = usize::MAX;
+
+/// A registered timer
+pub struct Entry {
+    deadline: Instant,
+    fired: AtomicBool,
+
+    /// `level * SLOTS + slot`, only modified by the wheel
+    location: AtomicUsize,
+
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Entry {
+    #[inline(always)]
+    pub fn new(deadline: Instant, waker: Waker) -> Arc<Entry> {
+        Arc::new(Entry {
+            deadline,
+            fired: AtomicBool::new(false),
+            location: AtomicUsize::new(NO_LOCATION),
+            waker: Mutex::new(Some(waker)),
+        })
+    }
+
+    #[inline(always)]
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    #[inline(always)]
+    pub fn is_fired(&self) -> bool {
+        self.fired.load(Ordering::Acquire)
+    }
+
+    #[inline(always)]
+    pub fn set_waker(&self, waker: &Waker) {
+        let mut w = self.waker.lock().unwrap();
+        match w.as_ref() {
+            Some(w) if w.will_wake(waker) => {}
+            _ => *w = Some(waker.clone()),
+        }
+    }
+
+    #[inline(always)]
+    pub fn fire(&self) -> Option<Waker> {
+        self.fired.store(true, Ordering::Release);
+        self.waker.lock().unwrap().take()
+    }
+}
+
+/// Hierarchical timer wheel with 1ms resolution
+///
+/// Every level has 64 slots, slot in level `n` cover `64^n` ms,
+/// entries in higher level are cascaded to the lower level when its slot is processed.
+pub struct Wheel {
+    base: Instant,
+
+    /// ms since base that already processed
+    elapsed: u64,
+
+    levels: Vec<Level>,
+}
+
+struct Level {
+    /// bitmap of non empty slots
+    occupied: u64,
+    slots: Vec<Vec<Arc<Entry>>>,
+}
+
+impl Wheel {
+    #[inline(always)]
+    pub fn new() -> Wheel {
+        Wheel {
+            base: Instant::now(),
+            elapsed: 0,
+            levels: (0..LEVELS)
+                .map(|_| Level {
+                    occupied: 0,
+                    slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// deadline in ms since base, rounded up, so the timer never fire early
+    #[inline(always)]
+    fn when(&self, deadline: Instant) -> u64 {
+        let d = deadline.saturating_duration_since(self.base);
+        d.as_nanos().div_ceil(1_000_000) as u64
+    }
+
+    /// return false if the entry is already expired, it is not inserted
+    #[inline(always)]
+    pub fn insert(&mut self, entry: Arc<Entry>) -> bool {
+        let when = self.when(entry.deadline);
+        if when <= self.elapsed {
+            return false;
+        }
+
+        // level is determined by the highest bit that differ from elapsed
+        let masked = std::cmp::min((self.elapsed ^ when) | (SLOTS as u64 - 1), MAX_MS);
+        let significant = 63 - masked.leading_zeros();
+        let level = (significant / LEVEL_BITS) as usize;
+        let slot = ((when >> (level as u32 * LEVEL_BITS)) as usize) & (SLOTS - 1);
+
+        entry
+            .location
+            .store(level * SLOTS + slot, Ordering::Relaxed);
+
+        let l = &mut self.levels[level];
+        l.slots[slot].push(entry);
+        l.occupied |= 1 << slot;
+
+        true
+    }
+
+    /// remove the entry from the wheel, it will never be fired
+    #[inline(always)]
+    pub fn remove(&mut self, entry: &Arc<Entry>) {
+        let location = entry.location.swap(NO_LOCATION, Ordering::Relaxed);
+        if location == NO_LOCATION {
+            return;
+        }
+
+        let l = &mut self.levels[location / SLOTS];
+        let slot = location % SLOTS;
+        let entries = &mut l.slots[slot];
+        if let Some(i) = entries.iter().position(|e| Arc::ptr_eq(e, entry)) {
+            entries.swap_remove(i);
+        }
+        if entries.is_empty() {
+            l.occupied &= !(1 << slot);
+        }
+    }
+
+    /// the earliest non empty slot, in (level, slot, ms since base)
+    // Option::is_none_or need rust 1.82
+    #[allow(clippy::unnecessary_map_or)]
+    #[inline(always)]
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        let mut next: Option<(usize, usize, u64)> = None;
+
+        for (level, l) in self.levels.iter().enumerate() {
+            let slot_range = 1u64 << (level as u32 * LEVEL_BITS);
+            let level_range = slot_range << LEVEL_BITS;
+
+            let now_slot = ((self.elapsed / slot_range) as usize) & (SLOTS - 1);
+
+            // only the top level can wrap around, its current slot is the furthest one
+            let first = if level == LEVELS - 1 {
+                (now_slot + 1) & (SLOTS - 1)
+            } else {
+                now_slot
+            };
+
+            let occupied = l.occupied.rotate_right(first as u32);
+            if occupied == 0 {
+                continue;
+            }
+
+            let slot = (first + occupied.trailing_zeros() as usize) & (SLOTS - 1);
+            let level_start = self.elapsed & !(level_range - 1);
+            let mut at = level_start + slot as u64 * slot_range;
+
+            // the slot is in the next window
+            if level == LEVELS - 1 && slot <= now_slot {
+                at += level_range;
+            }
+
+            let at = std::cmp::max(at, self.elapsed);
+
+            if next.map_or(true, |n| at < n.2) {
+                next = Some((level, slot, at));
+            }
+        }
+
+        next
+    }
+
+    /// instant when the wheel need to be processed again
+    #[inline(always)]
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.next_expiration()
+            .map(|(_, _, at)| self.base + Duration::from_millis(at))
+    }
+
+    /// fire all the expired entries, return their waker
+    #[inline(always)]
+    pub fn process(&mut self, now: Instant, wakers: &mut Vec<Waker>) {
+        let now = now.saturating_duration_since(self.base).as_millis() as u64;
+
+        while let Some((level, slot, at)) = self.next_expiration() {
+            if at > now {
+                break;
+            }
+
+            self.elapsed = at;
+
+            let l = &mut self.levels[level];
+            l.occupied &= !(1 << slot);
+            let entries = std::mem::take(&mut l.slots[slot]);
+
+            for entry in entries {
+                entry.location.store(NO_LOCATION, Ordering::Relaxed);
+
+                // not expired yet, cascade it to lower level
+                if let Err(entry) = self.reinsert(entry) {
+                    if let Some(waker) = entry.fire() {
+                        wakers.push(waker);
+                    }
+                }
+            }
+        }
+
+        if now > self.elapsed {
+            self.elapsed = now;
+        }
+    }
+
+    #[inline(always)]
+    fn reinsert(&mut self, entry: Arc<Entry>) -> Result<(), Arc<Entry>> {
+        if self.when(entry.deadline) <= self.elapsed {
+            Err(entry)
+        } else {
+            self.insert(entry);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Wake;
+
+    use super::*;
+
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn entry(wheel: &Wheel, ms: u64, extra_ns: u64) -> (Arc<Entry>, Arc<Counter>) {
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let deadline = wheel.base + Duration::from_millis(ms) + Duration::from_nanos(extra_ns);
+        (Entry::new(deadline, counter.clone().into()), counter)
+    }
+
+    fn process(wheel: &mut Wheel, ms: u64) -> usize {
+        let mut wakers = Vec::new();
+        wheel.process(wheel.base + Duration::from_millis(ms), &mut wakers);
+        let n = wakers.len();
+        wakers.into_iter().for_each(Waker::wake);
+        n
+    }
+
+    fn level(entry: &Entry) -> usize {
+        entry.location.load(Ordering::Relaxed) / SLOTS
+    }
+
+    #[test]
+    fn expired() {
+        let wheel = Wheel::new();
+        let (e, _) = entry(&wheel, 0, 0);
+        let mut wheel = wheel;
+        assert!(!wheel.insert(e));
+    }
+
+    #[test]
+    fn round_up() {
+        let mut wheel = Wheel::new();
+        let (e, counter) = entry(&wheel, 3, 1);
+        assert!(wheel.insert(e.clone()));
+        assert_eq!(
+            wheel.next_deadline(),
+            Some(wheel.base + Duration::from_millis(4))
+        );
+
+        // never fire early
+        assert_eq!(process(&mut wheel, 3), 0);
+        assert!(!e.is_fired());
+
+        assert_eq!(process(&mut wheel, 4), 1);
+        assert!(e.is_fired());
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+        assert_eq!(wheel.next_deadline(), None);
+    }
+
+    #[test]
+    fn cascade() {
+        let mut wheel = Wheel::new();
+        let (e, _) = entry(&wheel, 5000, 0);
+        assert!(wheel.insert(e.clone()));
+        assert_eq!(level(&e), 2);
+
+        // the slot of level 2 start at 4096, the entry is moved to lower level
+        assert_eq!(process(&mut wheel, 4999), 0);
+        assert!(level(&e) < 2);
+
+        assert_eq!(process(&mut wheel, 5000), 1);
+        assert!(e.is_fired());
+    }
+
+    #[test]
+    fn beyond_top_level() {
+        let mut wheel = Wheel::new();
+        let (e, _) = entry(&wheel, MAX_MS + 10, 0);
+        assert!(wheel.insert(e.clone()));
+
+        assert_eq!(level(&e), LEVELS - 1);
+
+        // re-inserted when processed
+        assert_eq!(process(&mut wheel, MAX_MS), 0);
+        assert!(!e.is_fired());
+
+        // crossing the top level window
+        let (e2, _) = entry(&wheel, MAX_MS + 1, 0);
+        assert!(wheel.insert(e2.clone()));
+        assert_eq!(process(&mut wheel, MAX_MS + 1), 1);
+        assert!(e2.is_fired());
+
+        assert_eq!(process(&mut wheel, MAX_MS + 10), 1);
+        assert!(e.is_fired());
+    }
+
+    #[test]
+    fn remove() {
+        let mut wheel = Wheel::new();
+        let (e, counter) = entry(&wheel, 100, 0);
+        assert!(wheel.insert(e.clone()));
+        wheel.remove(&e);
+
+        assert_eq!(wheel.next_deadline(), None);
+        assert_eq!(process(&mut wheel, 200), 0);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn fire_in_order() {
+        let mut wheel = Wheel::new();
+        let entries: Vec<_> = (1..500u64)
+            .map(|i| {
+                let mut ms = i * i * 7 % 300_000 + 1;
+
+                // some of them are beyond the top level
+                if i % 5 == 0 {
+                    ms *= 1_000_000;
+                }
+
+                let (e, _) = entry(&wheel, ms, 0);
+                assert!(wheel.insert(e.clone()));
+                (ms, e)
+            })
+            .collect();
+
+        let mut now = 0;
+        while let Some(next) = wheel.next_deadline() {
+            let ms = (next - wheel.base).as_millis() as u64;
+            assert!(ms >= now);
+            now = ms;
+            process(&mut wheel, now);
+
+            for (ms, e) in &entries {
+                assert_eq!(e.is_fired(), *ms <= now, "deadline {} at {}", ms, now);
+            }
+        }
+
+        assert!(entries.iter().all(|(_, e)| e.is_fired()));
+    }
+}
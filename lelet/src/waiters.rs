@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+use std::task::Waker;
+
+/// List of waiting task
+///
+/// Every waiter is identified by a key, so the waiter can update its waker
+/// or remove itself from the list when it is dropped.
+///
+/// This struct is not synchronized, it should be guarded by the owner lock.
+pub struct Waiters {
+    next_key: usize,
+    entries: VecDeque<(usize, Waker)>,
+}
+
+impl Waiters {
+    #[inline(always)]
+    pub fn new() -> Waiters {
+        Waiters {
+            next_key: 0,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// register the waker, or update it if `key` is still in the list
+    #[inline(always)]
+    pub fn register(&mut self, key: &mut Option<usize>, waker: &Waker) {
+        if let Some(k) = *key {
+            if let Some(entry) = self.entries.iter_mut().find(|e| e.0 == k) {
+                if !entry.1.will_wake(waker) {
+                    entry.1 = waker.clone();
+                }
+                return;
+            }
+        }
+
+        let k = self.next_key;
+        self.next_key = self.next_key.wrapping_add(1);
+        self.entries.push_back((k, waker.clone()));
+        key.replace(k);
+    }
+
+    /// remove the waker from the list
+    ///
+    /// return false if the waker is not in the list anymore (already notified)
+    #[inline(always)]
+    pub fn remove(&mut self, key: usize) -> bool {
+        match self.entries.iter().position(|e| e.0 == key) {
+            Some(i) => {
+                self.entries.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// return false if the waiter is not in the list anymore (already notified)
+    #[inline(always)]
+    pub fn contains(&self, key: usize) -> bool {
+        self.entries.iter().any(|e| e.0 == key)
+    }
+
+    /// wake the first waiter, return false if there is no waiter
+    #[inline(always)]
+    pub fn notify_one(&mut self) -> bool {
+        self.notify_first().is_some()
+    }
+
+    /// wake the first waiter, return its key, `None` if there is no waiter
+    #[inline(always)]
+    pub fn notify_first(&mut self) -> Option<usize> {
+        let (key, waker) = self.entries.pop_front()?;
+        waker.wake();
+        Some(key)
+    }
+
+    /// wake all the waiter
+    #[inline(always)]
+    pub fn notify_all(&mut self) {
+        self.entries.drain(..).for_each(|(_, waker)| waker.wake());
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}